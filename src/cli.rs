@@ -0,0 +1,175 @@
+//! ゲームを起動せずに行うコマンドライン操作.
+//! `rhythm_2 import <file> [out_dir]`のように最初の引数でコマンドを指定する.
+
+use std::{fs, path::Path};
+
+use itertools::Itertools;
+
+use crate::resources::{
+    analysis::{analyze_chart, ChartAnalysis},
+    asset_loader::SONG_LIST_PATH,
+    import::{
+        bms::import_bms,
+        osu::{export_osu, import_osu},
+        stepmania::import_stepmania,
+        ImportError, ImportedChart,
+    },
+    song::{SongConfig, SongConfigParser},
+    song_list::SongDataParser,
+    validation::validate_song_config,
+};
+
+const USAGE: &str = "usage:
+  rhythm_2 import <file.sm|file.ssc|file.osu|file.bms|file.bme> [out_dir]
+  rhythm_2 export <chart.yaml> [out.osu]
+  rhythm_2 analyze [chart.yaml ...]";
+
+/// 拡張子から変換方法を選んで譜面を変換する
+fn import_file(path: &Path) -> Result<Vec<ImportedChart>, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let result = match ext.as_str() {
+        "sm" | "ssc" => import_stepmania(&source),
+        "osu" => import_osu(&source).map(|chart| vec![chart]),
+        "bms" | "bme" | "bml" => import_bms(&source).map(|chart| vec![chart]),
+        _ => Err(ImportError::Unsupported(format!(".{} files", ext))),
+    };
+    result.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// 変換した譜面を`<元のファイル名>_<難易度>.yaml`として書き出し, 曲一覧に追記する内容を表示する
+fn import(args: &[String]) -> Result<(), String> {
+    let path = Path::new(args.first().ok_or(USAGE)?);
+    let out_dir = Path::new(args.get(1).map_or(".", |dir| dir.as_str()));
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("chart");
+
+    let charts = import_file(path)?;
+    println!("charts:");
+    for chart in charts {
+        let difficulty = if chart.difficulty.is_empty() {
+            "Normal".to_string()
+        } else {
+            chart.difficulty.clone()
+        };
+        let filename = format!("{}_{}.yaml", stem, difficulty.to_lowercase());
+        for warning in chart.warnings.iter() {
+            eprintln!("warning: {}: {}", filename, warning);
+        }
+        let data_str = serde_yaml::to_string(&chart.config).map_err(|e| e.to_string())?;
+        fs::write(out_dir.join(&filename), data_str).map_err(|e| format!("{}: {}", filename, e))?;
+        println!(
+            "  - {{ name: \"{}\", level: {}, config_file_name: \"{}\" }}",
+            difficulty, chart.level, filename
+        );
+    }
+    Ok(())
+}
+
+/// yamlの譜面ファイルを読み込む
+fn read_chart(path: &Path) -> Result<SongConfig, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_yaml::from_str::<SongConfigParser>(&source)
+        .map(SongConfig::from)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// 譜面をosu!maniaの譜面として書き出す. 出力先の省略時は譜面と同じ場所に拡張子を変えて書き出す.
+fn export(args: &[String]) -> Result<(), String> {
+    let path = Path::new(args.first().ok_or(USAGE)?);
+    let out_path = args.get(1).map_or_else(
+        || path.with_extension("osu"),
+        |out| Path::new(out).to_path_buf(),
+    );
+    let version = path.file_stem().and_then(|s| s.to_str()).unwrap_or("chart");
+
+    let data_str = export_osu(&read_chart(path)?, version);
+    fs::write(&out_path, data_str).map_err(|e| format!("{}: {}", out_path.display(), e))?;
+    println!("exported to {}", out_path.display());
+    Ok(())
+}
+
+fn print_analysis(analysis: &ChartAnalysis) {
+    println!("  notes: {}", analysis.note_count);
+    println!(
+        "  nps: average {:.2}, peak {}",
+        analysis.average_nps, analysis.peak_nps
+    );
+    println!(
+        "  patterns: {} jacks, {} trills, {} stairs",
+        analysis.jacks, analysis.trills, analysis.stairs
+    );
+    println!("  rating: {:.1}", analysis.rating);
+    println!("  nps curve: {}", analysis.nps_curve.iter().join(" "));
+}
+
+/// 譜面の検査で見つかった問題点を表示する
+fn print_issues(path: &str, config: &SongConfig) {
+    for issue in validate_song_config(config) {
+        let level = if issue.is_error() { "error" } else { "warning" };
+        eprintln!("{}: {}: {}", level, path, issue);
+    }
+}
+
+/// 譜面の難しさを見積もって表示する. 譜面の検査で見つかった問題点もあわせて表示する.
+/// 譜面を指定しなければ曲一覧の全譜面について, 付けられたレベルと見積もりを並べて表示する.
+fn analyze(args: &[String]) -> Result<(), String> {
+    if !args.is_empty() {
+        for path in args {
+            let config = read_chart(Path::new(path))?;
+            println!("{}", path);
+            print_issues(path, &config);
+            print_analysis(&analyze_chart(&config));
+        }
+        return Ok(());
+    }
+
+    let list_path = Path::new("assets").join(SONG_LIST_PATH);
+    let source =
+        fs::read_to_string(&list_path).map_err(|e| format!("{}: {}", list_path.display(), e))?;
+    let songs = serde_yaml::from_str::<Vec<SongDataParser>>(&source)
+        .map_err(|e| format!("{}: {}", list_path.display(), e))?;
+    println!(
+        "{:<24} {:<10} {:>5} {:>7}",
+        "song", "chart", "level", "rating"
+    );
+    for song in songs {
+        for chart in song.charts {
+            let path = Path::new("assets/songs").join(&chart.config_file_name);
+            let rating = read_chart(&path)
+                .map(|config| {
+                    print_issues(&path.display().to_string(), &config);
+                    format!("{:.1}", analyze_chart(&config).rating)
+                })
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    "-".to_string()
+                });
+            println!(
+                "{:<24} {:<10} {:>5} {:>7}",
+                song.name, chart.name, chart.level, rating
+            );
+        }
+    }
+    Ok(())
+}
+
+/// コマンドが指定されていれば実行して`true`を返す. 指定されていなければゲームを起動するので`false`を返す.
+pub fn run() -> bool {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.first().map(|s| s.as_str()) {
+        None => return false,
+        Some("import") => import(&args[1..]),
+        Some("export") => export(&args[1..]),
+        Some("analyze") => analyze(&args[1..]),
+        Some(_) => Err(USAGE.to_string()),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    true
+}
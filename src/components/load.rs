@@ -2,3 +2,7 @@ use bevy::prelude::*;
 
 #[derive(Component, Clone, Copy, Debug)]
 pub struct NowLoadingText;
+
+/// 読み込みエラー画面のエンティティを示すマーカー
+#[derive(Component)]
+pub struct LoadErrorObject;
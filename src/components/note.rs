@@ -1,56 +1,119 @@
 use bevy::prelude::*;
 
-use crate::{constants::LANE_WIDTH, resources::note::NoteType};
+use crate::{
+    constants::{HOLD_TICKS_PER_BEAT, LANE_WIDTH},
+    resources::{beat_position::BeatPosition, note::NoteType},
+};
 
 /// ゲームが使う情報を入れた構造体. 取得時の受け渡しのためコンポーネントとして使う.
 #[derive(Component, Debug, Clone)]
 pub struct NoteInfo {
     pub note_type: NoteType,
     pub bar: u32,
-    pub beat: f64,
+    pub beat: BeatPosition,
     pub spawn_time: f64,
     pub target_time: f64,
+    /// ロングノーツ・ロールノーツの終点が判定線に到達する時刻. 途中のBPM変更を考慮して読み込み時に求める.
+    /// その他のノーツでは`target_time`と同じ.
+    pub end_time: f64,
+    /// ロングノーツ・ロールノーツを押している間の加点の時刻. その他のノーツでは空.
+    pub tick_times: Vec<f64>,
+    /// 判定線に到達する時点でのBPM
+    pub bpm: f32,
+    /// 取得したときに鳴らすキー音の番号
+    pub sound: Option<u32>,
+}
+impl NoteInfo {
+    /// ロングノーツ・ロールノーツを押している間の時刻`time`から`beats`拍進んだ時刻を求める.
+    /// 始点と加点の時刻を拍の目盛りとして補間するので, 途中でBPMが変わってもその間のBPMで進む.
+    /// 加点のない短いノーツでは判定線に到達する時点でのBPMで求める.
+    pub fn time_after_beats(&self, time: f64, beats: f64) -> f64 {
+        let grid = std::iter::once(self.target_time)
+            .chain(self.tick_times.iter().copied())
+            .collect::<Vec<_>>();
+        if grid.len() < 2 {
+            return time + beats * 60.0 / self.bpm as f64;
+        }
+        // 目盛りの範囲外は最初か最後の区間の間隔で延ばす
+        let segment = |i: usize| {
+            let i = i.min(grid.len() - 2);
+            (i, grid[i], grid[i + 1] - grid[i])
+        };
+        let (i, start, span) = segment(grid.partition_point(|&t| t <= time).saturating_sub(1));
+        let tick = i as f64 + (time - start) / span + beats * HOLD_TICKS_PER_BEAT as f64;
+        let (i, start, span) = segment(tick.max(0.0) as usize);
+        start + (tick - i as f64) * span
+    }
 }
 
-/// 鍵盤レーン
+/// 鍵盤レーン. 鍵盤番号と, 譜面全体の鍵盤数を持つ.
 #[derive(Component, Clone, Copy, Debug)]
-pub struct KeyLane(pub i32);
+pub struct KeyLane {
+    pub num: i32,
+    pub lane_num: u8,
+}
 impl KeyLane {
-    /// 鍵盤の数
-    pub const KEY_NUM: u8 = 4;
+    /// 譜面で指定がない場合の鍵盤の数
+    pub const DEFAULT_KEY_NUM: u8 = 4;
+    /// 対応している鍵盤数の最小値
+    pub const MIN_KEY_NUM: u8 = 4;
+    /// 対応している鍵盤数の最大値
+    pub const MAX_KEY_NUM: u8 = 7;
+
+    pub fn new(num: i32, lane_num: u8) -> Self {
+        Self { num, lane_num }
+    }
+
+    /// 鍵盤数と番号から入力可能キーを返す. 鍵盤によって押せるキーの数は異なる.
+    fn get_key_list(&self) -> &'static [KeyCode] {
+        match (self.lane_num, self.num) {
+            (4, 0) => &[KeyCode::C, KeyCode::D, KeyCode::S],
+            (4, 1) => &[KeyCode::V, KeyCode::F, KeyCode::G],
+            (4, 2) => &[KeyCode::N, KeyCode::J, KeyCode::H],
+            (4, 3) => &[KeyCode::M, KeyCode::K, KeyCode::L],
 
-    /// 番号から入力可能キーを返す
-    fn get_key_list(&self) -> [KeyCode; 3] {
-        match self.0 {
-            0 => [KeyCode::C, KeyCode::D, KeyCode::S],
-            1 => [KeyCode::V, KeyCode::F, KeyCode::G],
-            2 => [KeyCode::N, KeyCode::J, KeyCode::H],
-            3 => [KeyCode::M, KeyCode::K, KeyCode::L],
-            _ => [KeyCode::Return, KeyCode::Return, KeyCode::Return],
+            (5, 0) => &[KeyCode::D, KeyCode::C, KeyCode::S],
+            (5, 1) => &[KeyCode::F, KeyCode::V, KeyCode::G],
+            (5, 2) => &[KeyCode::Space, KeyCode::B],
+            (5, 3) => &[KeyCode::J, KeyCode::N, KeyCode::H],
+            (5, 4) => &[KeyCode::K, KeyCode::M, KeyCode::L],
+
+            (6, 0) => &[KeyCode::S, KeyCode::X, KeyCode::A],
+            (6, 1) => &[KeyCode::D, KeyCode::C],
+            (6, 2) => &[KeyCode::F, KeyCode::V, KeyCode::G],
+            (6, 3) => &[KeyCode::J, KeyCode::N, KeyCode::H],
+            (6, 4) => &[KeyCode::K, KeyCode::M],
+            (6, 5) => &[KeyCode::L, KeyCode::Comma, KeyCode::Semicolon],
+
+            (7, 0) => &[KeyCode::S, KeyCode::X, KeyCode::A],
+            (7, 1) => &[KeyCode::D, KeyCode::C],
+            (7, 2) => &[KeyCode::F, KeyCode::V, KeyCode::G],
+            (7, 3) => &[KeyCode::Space, KeyCode::B],
+            (7, 4) => &[KeyCode::J, KeyCode::N, KeyCode::H],
+            (7, 5) => &[KeyCode::K, KeyCode::M],
+            (7, 6) => &[KeyCode::L, KeyCode::Comma, KeyCode::Semicolon],
+
+            _ => &[KeyCode::Return],
         }
     }
     /// 番号とキーを結びつけ, 指定された鍵盤番号に対応するキーが今押されたかどうかを取得.
     pub fn key_just_pressed(&self, input: &Input<KeyCode>) -> bool {
-        input.any_just_pressed(self.get_key_list())
+        input.any_just_pressed(self.get_key_list().iter().copied())
     }
     pub fn key_pressed(&self, input: &Input<KeyCode>) -> bool {
-        input.any_pressed(self.get_key_list())
-    }
-    pub fn key_just_released(&self, input: &Input<KeyCode>) -> bool {
-        input.any_just_released(self.get_key_list())
+        input.any_pressed(self.get_key_list().iter().copied())
     }
     /// 複数の入力が可能な鍵盤に対して, 確実に全ての入力をリセットする.
     pub fn reset_key(&self, input: &mut Input<KeyCode>) {
-        let keys = self.get_key_list();
-        for key in keys {
-            input.reset(key);
+        for key in self.get_key_list() {
+            input.reset(*key);
         }
     }
 
-    /// x_coordをi32から取得
-    pub fn x_coord_from_num(num: i32) -> f32 {
+    /// 鍵盤数と鍵盤番号からx座標を取得. 全体が画面中央に来るように並べる.
+    pub fn x_coord_from_num(num: i32, lane_num: u8) -> f32 {
         let half_width = LANE_WIDTH / 2.0;
-        0.0 - (Self::KEY_NUM - 1) as f32 * half_width + LANE_WIDTH * num as f32
+        0.0 - (lane_num - 1) as f32 * half_width + LANE_WIDTH * num as f32
     }
 }
 
@@ -70,13 +133,22 @@ pub enum LongNoteState {
     /// 正常終了
     End,
 }
-/// ロングノーツにつけて演出を行う.
+/// ロングノーツにつけて演出を行う. ロールノーツにも使う.
 #[derive(Component, Default)]
 pub struct LongNote {
     pub state: LongNoteState,
+    /// ロールノーツを最後に叩いた時刻
+    pub last_tap: f64,
+    /// 判定済みの押している間の加点の数. 次に判定する加点の`NoteInfo::tick_times`での位置になる.
+    pub next_tick: u32,
 }
 impl LongNote {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// 長さ（拍）から押している間の加点の回数を求める. 終点ちょうどの分は終点の評価に含めるので数えない.
+    pub fn tick_num(length: f32) -> u32 {
+        ((length * HOLD_TICKS_PER_BEAT as f32).ceil() as u32).saturating_sub(1)
+    }
 }
@@ -66,6 +66,15 @@ impl PatternReceptor for DoubleTapReceptor {
                 length: _,
                 id: _,
             } => {}
+            NoteType::BpmChange { bpm: _ }
+            | NoteType::BeatChange { beat: _ }
+            | NoteType::KeySound { sound: _ }
+            | NoteType::Mine { key: _ }
+            | NoteType::Roll {
+                key: _,
+                length: _,
+                id: _,
+            } => {}
         }
     }
 
@@ -1,23 +1,23 @@
 use bevy::prelude::*;
 
 use super::{NotesPattern, PatternReceptor};
-use crate::{events::CatchNoteEvent, resources::note::NoteType};
+use crate::{components::note::KeyLane, events::CatchNoteEvent, resources::note::NoteType};
 
 /// 鍵盤にノーツを収めるにはbool配列を使うが, usizeのインデックスと鍵盤番号i32の対応を行う必要がある.
 /// そのため鍵盤番号が連番なのを前提として最小の番号がどれになるかを指定する.
 const KEY_FIRST_NUM: i32 = 0;
 
-/// 4点同時押し
+/// 全鍵盤同時押し
 #[derive(Component)]
 pub struct FullSyncReceptor {
     first_time: f64,
-    lane: [bool; 4],
+    lane: Vec<bool>,
 }
 impl Default for FullSyncReceptor {
     fn default() -> Self {
         Self {
             first_time: 0.0,
-            lane: [false; 4],
+            lane: vec![false; KeyLane::DEFAULT_KEY_NUM as usize],
         }
     }
 }
@@ -32,9 +32,16 @@ impl PatternReceptor for FullSyncReceptor {
         boolean_string(&self.lane)
     }
 
+    fn with_lane_num(lane_num: u8) -> Self {
+        Self {
+            lane: vec![false; lane_num as usize],
+            ..Default::default()
+        }
+    }
+
     #[inline(always)]
     fn init(&mut self) {
-        self.lane = [false; 4];
+        self.lane = vec![false; self.lane.len()];
     }
 
     fn initialized(&self) -> bool {
@@ -76,6 +76,11 @@ pub trait PatternReceptor: Default + Component {
     #[cfg(feature = "debug")]
     fn debug_display(&self) -> String;
 
+    /// 譜面の鍵盤数を指定して生成する. 鍵盤数に依存しないレセプタはデフォルト実装のままでよい.
+    fn with_lane_num(_lane_num: u8) -> Self {
+        Self::default()
+    }
+
     /// 初期化を行う. デフォルト状態に戻す実装がされているが, 何をもって初期化とするか上書きして実装することもできる.
     fn init(&mut self) {
         *self = Self::default();
@@ -1,7 +1,10 @@
 use bevy::prelude::*;
 
 use super::{NotesPattern, PatternReceptor};
-use crate::{constants::MISS_THR, events::CatchNoteEvent, resources::note::NoteType};
+use crate::{
+    components::note::KeyLane, constants::MISS_THR, events::CatchNoteEvent,
+    resources::note::NoteType,
+};
 
 /// 3列の右向き階段.
 #[derive(Component)]
@@ -9,14 +12,14 @@ pub struct StepLeftReceptor {
     last_lane: i32,
     /// 最後に入力を許容した時刻
     last_time: f64,
-    lane: [bool; 4],
+    lane: Vec<bool>,
 }
 impl Default for StepLeftReceptor {
     fn default() -> Self {
         Self {
             last_lane: -1,
             last_time: 0.0,
-            lane: [false; 4],
+            lane: vec![false; KeyLane::DEFAULT_KEY_NUM as usize],
         }
     }
 }
@@ -36,14 +39,21 @@ impl PatternReceptor for StepLeftReceptor {
         )
     }
 
+    fn with_lane_num(lane_num: u8) -> Self {
+        Self {
+            lane: vec![false; lane_num as usize],
+            ..Default::default()
+        }
+    }
+
     #[inline]
     fn init(&mut self) {
         self.last_lane = -1;
-        self.lane = [false; 4];
+        self.lane = vec![false; self.lane.len()];
     }
 
     fn initialized(&self) -> bool {
-        !self.lane[2] && !self.lane[3]
+        self.lane.iter().all(|&e| !e)
     }
 
     fn initialize_or_defer(&mut self, current_time: f64, bpm: f32) {
@@ -55,8 +65,8 @@ impl PatternReceptor for StepLeftReceptor {
     fn input(&mut self, note_ev: &CatchNoteEvent) {
         if let NoteType::Normal { key } | NoteType::AdLib { key } = note_ev.note.note_type {
             let real_sec = note_ev.real_time;
-            // 2, 3がfalseなら受付状態で, 2, 3が来たら開始
-            if self.initialized() && (key == 2 || key == 3) {
+            // すべてfalseなら受付状態で, 左に2つ以上鍵盤が残っている位置から開始
+            if self.initialized() && key >= 2 {
                 self.last_time = real_sec;
                 self.last_lane = key;
                 self.lane[key as usize] = true;
@@ -1,7 +1,10 @@
 use bevy::prelude::*;
 
 use super::{NotesPattern, PatternReceptor};
-use crate::{constants::MISS_THR, events::CatchNoteEvent, resources::note::NoteType};
+use crate::{
+    components::note::KeyLane, constants::MISS_THR, events::CatchNoteEvent,
+    resources::note::NoteType,
+};
 
 /// 3列の右向き階段.
 #[derive(Component)]
@@ -9,14 +12,14 @@ pub struct StepRightReceptor {
     last_lane: i32,
     /// 最後に入力を許容した時刻
     last_time: f64,
-    lane: [bool; 4],
+    lane: Vec<bool>,
 }
 impl Default for StepRightReceptor {
     fn default() -> Self {
         Self {
             last_lane: -1,
             last_time: 0.0,
-            lane: [false; 4],
+            lane: vec![false; KeyLane::DEFAULT_KEY_NUM as usize],
         }
     }
 }
@@ -36,14 +39,21 @@ impl PatternReceptor for StepRightReceptor {
         )
     }
 
+    fn with_lane_num(lane_num: u8) -> Self {
+        Self {
+            lane: vec![false; lane_num as usize],
+            ..Default::default()
+        }
+    }
+
     #[inline]
     fn init(&mut self) {
         self.last_lane = -1;
-        self.lane = [false; 4];
+        self.lane = vec![false; self.lane.len()];
     }
 
     fn initialized(&self) -> bool {
-        !self.lane[0] && !self.lane[1]
+        self.lane.iter().all(|&e| !e)
     }
 
     fn initialize_or_defer(&mut self, current_time: f64, bpm: f32) {
@@ -56,8 +66,8 @@ impl PatternReceptor for StepRightReceptor {
     fn input(&mut self, note_ev: &CatchNoteEvent) {
         if let NoteType::Normal { key } | NoteType::AdLib { key } = note_ev.note.note_type {
             let real_sec = note_ev.real_time;
-            // 0, 1がfalseなら受付状態で, 0, 1が来たら開始
-            if self.initialized() && (key == 0 || key == 1) {
+            // すべてfalseなら受付状態で, 右に2つ以上鍵盤が残っている位置から開始
+            if self.initialized() && (key as usize + 2) < self.lane.len() {
                 self.last_time = real_sec;
                 self.last_lane = key;
                 self.lane[key as usize] = true;
@@ -4,3 +4,7 @@ use bevy::prelude::*;
 pub struct ScrollingList {
     pub position: f32,
 }
+
+/// タイミングの詳細（誤差の分布と推移）を表示するパネル. Tキーで表示を切り替える.
+#[derive(Component)]
+pub struct TimingStatsPanel;
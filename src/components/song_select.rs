@@ -18,5 +18,20 @@ pub struct SongSelectCard(pub usize);
 #[derive(Component)]
 pub struct DifficultyText;
 
+/// 選択中のゲージの種類とノーフェイルの設定を表示するテキスト
+#[derive(Component)]
+pub struct GaugeText;
+
+/// ヒットエラーバーを表示するかどうかを表示するテキスト
+#[derive(Component)]
+pub struct HitErrorBarText;
+
+/// 曲カード内の, 選択中の譜面名とレベルを表示するテキスト
+#[derive(Component)]
+pub struct ChartText;
+
 #[derive(Component)]
 pub struct SpeedSettingNode;
+
+#[derive(Component)]
+pub struct OffsetSettingNode;
@@ -16,9 +16,21 @@ pub struct ChartInfoNode;
 #[derive(Component)]
 pub struct ScoreText;
 
+/// 現在のコンボ数を表示するテキスト
+#[derive(Component)]
+pub struct ComboText;
+
 #[derive(Component)]
 pub struct TargetLine;
 
+/// ヒットエラーバーに叩くたびに描く目盛り. 時間が経つと薄くなって消える.
+#[derive(Component)]
+pub struct HitErrorTick;
+
+/// ライフゲージの残量を表すバー. 下端を基準に高さを変える.
+#[derive(Component)]
+pub struct LifeGaugeBar;
+
 #[derive(Component)]
 pub struct LaneLine;
 
@@ -12,14 +12,35 @@ pub const NOTE_SPAWN_Y: f32 = 400.0;
 /// ノーツをとる判定線のY座標.
 pub const TARGET_Y: f32 = -200.0;
 
-/// ノーツをとるときにミスになるまでの時刻誤差（秒）
+/// 配置パターンの判定で許容する時刻誤差（秒）. ノーツ自体の判定幅は`JudgeProfile`で決まる.
 pub const MISS_THR: f64 = 0.1;
 
+/// ロールノーツを叩き直さなければならない間隔（拍）
+pub const ROLL_TAP_INTERVAL: f32 = 1.0;
+
+/// ロングノーツ・ロールノーツを押している間に加点する回数（一拍あたり）.
+/// 始点からこの間隔で加点するので, フレームレートによらず譜面ごとに加点の回数が決まる.
+pub const HOLD_TICKS_PER_BEAT: u32 = 2;
+
 /// 出現位置から判定線までの距離
 pub const DISTANCE: f32 = TARGET_Y - NOTE_SPAWN_Y;
 
 /// ゲームステートに移行してから曲が再生されるまでの時間（秒）
 pub const MUSIC_PLAY_PRECOUNT: f64 = 4.0;
 
+/// エディタで入力したノーツを寄せるグリッド（一拍の分割数）. 16分音符と8分3連符の両方を表せる12とする.
+pub const EDITOR_GRID_DIVISION: u32 = 12;
+
 /// 鍵盤レーンの幅（px）
 pub const LANE_WIDTH: f32 = 100.0;
+
+/// ライフゲージの大きさ（px）. レーンの右に判定線から上へ伸びるように置く.
+pub const LIFE_GAUGE_WIDTH: f32 = 12.0;
+pub const LIFE_GAUGE_HEIGHT: f32 = 500.0;
+
+/// ヒットエラーバーの幅（px）. 両端が判定幅の端（ミスになる誤差）にあたる.
+pub const HIT_ERROR_BAR_WIDTH: f32 = 300.0;
+/// ヒットエラーバーの判定線からの距離（px）
+pub const HIT_ERROR_BAR_OFFSET: f32 = 30.0;
+/// ヒットエラーバーの目盛りが消えるまでのフレーム数
+pub const HIT_ERROR_TICK_LIFETIME: u32 = 120;
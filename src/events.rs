@@ -3,7 +3,11 @@ use bevy::prelude::*;
 
 use crate::{
     components::{note::NoteInfo, receptor::NotesPattern},
-    resources::{editor::EditNote, score::CatchEval},
+    resources::{
+        editor::EditNote,
+        judge::JudgeWindow,
+        score::{CatchEval, JudgePoint},
+    },
 };
 
 // /// ノーツを取り逃したときのイベント
@@ -15,12 +19,41 @@ use crate::{
 pub struct NoteEvalEvent {
     pub eval: CatchEval,
     pub note: NoteInfo,
+    /// ノーツのどの部分に対する評価か
+    pub point: JudgePoint,
+    /// 叩いた時刻の誤差（秒）. 正なら遅い. 叩いた時刻で評価したときのみ持つ.
+    pub timing_error: Option<f64>,
 }
 impl NoteEvalEvent {
-    pub fn new(note: &NoteInfo, real_time: f64) -> Self {
+    /// ノーツ（ロングノーツでは始点）を取得した時刻から評価する
+    pub fn new(note: &NoteInfo, real_time: f64, window: &JudgeWindow) -> Self {
         Self {
-            eval: CatchEval::new(note.target_time, real_time),
+            timing_error: Some(real_time - note.target_time),
+            ..Self::head(note, window.eval(note.target_time, real_time))
+        }
+    }
+    pub fn head(note: &NoteInfo, eval: CatchEval) -> Self {
+        Self {
+            eval,
+            note: note.clone(),
+            point: JudgePoint::Head,
+            timing_error: None,
+        }
+    }
+    pub fn tail(note: &NoteInfo, eval: CatchEval) -> Self {
+        Self {
+            eval,
+            note: note.clone(),
+            point: JudgePoint::Tail,
+            timing_error: None,
+        }
+    }
+    pub fn tick(note: &NoteInfo, eval: CatchEval) -> Self {
+        Self {
+            eval,
             note: note.clone(),
+            point: JudgePoint::Tick,
+            timing_error: None,
         }
     }
 }
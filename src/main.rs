@@ -1,3 +1,4 @@
+mod cli;
 mod components;
 mod constants;
 mod events;
@@ -15,8 +16,9 @@ use events::add_events_to_game;
 use resources::game_state::NextAppState;
 use systems::{
     audio::GameAudioPlugin, editor::ChartEditorPlugin, home_menu::HomeMenuPlugin, load::LoadPlugin,
-    note::NotePlugin, receptor::PatternReceptorPlugin, result_screen::ResultScreenPlugin,
-    score::ScorePlugin, song_select::SongSelectStatePlugin, timer::TimersPlugin, ui::GameUiPlugin,
+    load_error::LoadErrorPlugin, note::NotePlugin, receptor::PatternReceptorPlugin,
+    result_screen::ResultScreenPlugin, score::ScorePlugin, song_select::SongSelectStatePlugin,
+    timer::TimersPlugin, ui::GameUiPlugin,
 };
 
 #[cfg(feature = "debug")]
@@ -29,6 +31,8 @@ pub enum AppState {
     Loading,
     Game,
     Editor,
+    /// 曲一覧や譜面の読み込みに失敗したときのエラー画面
+    LoadError,
 }
 
 fn global_setup(mut commands: Commands) {
@@ -37,6 +41,11 @@ fn global_setup(mut commands: Commands) {
 }
 
 fn main() {
+    // 譜面の変換などのコマンドが指定されていればゲームは起動しない
+    if cli::run() {
+        return;
+    }
+
     let window = WindowDescriptor {
         title: "rhythm".to_string(),
         width: SCREEN_WIDTH,
@@ -53,10 +62,18 @@ fn main() {
     #[cfg(feature = "debug")]
     app.add_system(bevy::window::close_on_esc);
 
-    app.add_plugins(DefaultPlugins.set(WindowPlugin {
-        window,
-        ..Default::default()
-    }));
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                window,
+                ..Default::default()
+            })
+            // 譜面や曲一覧のファイルの変更を検知して読み込み直す
+            .set(AssetPlugin {
+                watch_for_changes: true,
+                ..Default::default()
+            }),
+    );
     app.add_plugin(AudioPlugin);
     // ステート初期化
     // 次に向かいたいステートをセットしてからローディングステートで開始する.
@@ -77,6 +94,7 @@ fn main() {
     app.add_plugin(HomeMenuPlugin);
     app.add_plugin(SongSelectStatePlugin);
     app.add_plugin(ResultScreenPlugin);
+    app.add_plugin(LoadErrorPlugin);
     // app.add_plugin(ShadersPlugin);
 
     app.add_plugin(ChartEditorPlugin);
@@ -0,0 +1,229 @@
+//! 譜面の難しさを見積もる.
+//! ノーツの密度と, 縦連・トリル・階段といった配置の数から一つの数値（レーティング）を求める.
+//! 譜面作者が付けたレベルが妥当かどうかを確かめるための目安で, 選曲画面とコマンドラインから使う.
+
+use itertools::Itertools;
+
+use crate::components::note::NoteInfo;
+
+use super::{note::NoteTypeKey, song::SongConfig, timing::to_notes_info_from_notes_spawn};
+
+/// 同時押しとみなす時刻の差（秒）
+const CHORD_EPSILON: f64 = 0.001;
+/// 配置の一部とみなす前のノーツとの間隔の上限（秒）. これより空いていれば配置が途切れる.
+const PATTERN_INTERVAL: f64 = 0.3;
+/// トリルとみなす最小のノーツ数
+const MIN_TRILL_LENGTH: usize = 4;
+/// 階段とみなす最小のノーツ数
+const MIN_STAIR_LENGTH: usize = 3;
+/// 持続的な密度として平均を取る, 密度の高い区間の割合
+const SUSTAINED_RATIO: f64 = 0.25;
+/// 配置一つあたりのレーティングの上乗せ. ノーツ数で割ってから掛ける.
+const JACK_WEIGHT: f64 = 1.0;
+const TRILL_WEIGHT: f64 = 0.5;
+const STAIR_WEIGHT: f64 = 0.3;
+/// レーティングを付けられたレベルと同じくらいの数値に揃えるための係数
+const RATING_SCALE: f64 = 0.5;
+
+/// 譜面の分析結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartAnalysis {
+    /// 叩くノーツの数（アドリブノーツは含めない）
+    pub note_count: usize,
+    /// 最初のノーツから1秒ごとに区切った区間のノーツ数
+    pub nps_curve: Vec<u32>,
+    /// 任意の1秒間に含まれるノーツ数の最大値
+    pub peak_nps: u32,
+    /// 最初のノーツから最後のノーツまでの平均の秒間ノーツ数
+    pub average_nps: f64,
+    /// 縦連（同じ鍵盤の連打）の数
+    pub jacks: u32,
+    /// トリルの数
+    pub trills: u32,
+    /// 階段の数
+    pub stairs: u32,
+    /// 難しさの見積もり
+    pub rating: f64,
+}
+
+/// 叩く必要のあるノーツの鍵盤を返す
+fn playable_key(note: &NoteInfo) -> Option<i32> {
+    match NoteTypeKey::from(&note.note_type) {
+        NoteTypeKey::Normal { key } | NoteTypeKey::Long { key } | NoteTypeKey::Roll { key } => {
+            Some(key)
+        }
+        _ => None,
+    }
+}
+
+/// 三つ続いた鍵盤の並びのうち, 最後の鍵盤で配置が続いているかを判定する関数が
+/// 連続して成り立つ区間を数える. `min_length`はノーツ数で指定する.
+fn count_runs(keys: &[i32], min_length: usize, continues: fn(&[i32]) -> bool) -> u32 {
+    keys.windows(3)
+        .map(continues)
+        .group_by(|&ok| ok)
+        .into_iter()
+        .filter_map(|(ok, group)| ok.then(|| group.count()))
+        .filter(|&windows| windows + 2 >= min_length)
+        .count() as u32
+}
+
+/// `a, b, a, b, ...`
+fn is_trill(keys: &[i32]) -> bool {
+    keys[0] == keys[2] && keys[0] != keys[1]
+}
+
+/// `0, 1, 2, ...`や`3, 2, 1, ...`のように隣の鍵盤へ同じ向きに進む
+fn is_stair(keys: &[i32]) -> bool {
+    (keys[1] - keys[0]).abs() == 1 && keys[2] - keys[1] == keys[1] - keys[0]
+}
+
+/// 判定時刻の付いたノーツ列を分析する
+pub fn analyze_notes(notes: &[NoteInfo]) -> ChartAnalysis {
+    // 同時押しをまとめた行の列. 各行は時刻と鍵盤の列.
+    let rows = notes
+        .iter()
+        .filter_map(|note| playable_key(note).map(|key| (note.target_time, key)))
+        .sorted_by(|a, b| a.0.total_cmp(&b.0))
+        .fold(Vec::<(f64, Vec<i32>)>::new(), |mut rows, (time, key)| {
+            match rows.last_mut() {
+                Some((row_time, keys)) if time - *row_time < CHORD_EPSILON => keys.push(key),
+                _ => rows.push((time, vec![key])),
+            }
+            rows
+        });
+    let times = rows
+        .iter()
+        .flat_map(|(time, keys)| std::iter::repeat_n(*time, keys.len()))
+        .collect_vec();
+    let note_count = times.len();
+    let (Some(&first), Some(&last)) = (times.first(), times.last()) else {
+        return ChartAnalysis {
+            note_count: 0,
+            nps_curve: vec![],
+            peak_nps: 0,
+            average_nps: 0.0,
+            jacks: 0,
+            trills: 0,
+            stairs: 0,
+            rating: 0.0,
+        };
+    };
+
+    // 密度
+    let mut nps_curve = vec![0u32; (last - first).floor() as usize + 1];
+    for time in times.iter() {
+        nps_curve[(time - first).floor() as usize] += 1;
+    }
+    let mut window_start = 0;
+    let mut peak_nps = 0;
+    for (i, time) in times.iter().enumerate() {
+        while time - times[window_start] >= 1.0 {
+            window_start += 1;
+        }
+        peak_nps = peak_nps.max((i - window_start + 1) as u32);
+    }
+    let average_nps = note_count as f64 / (last - first).max(1.0);
+
+    // 配置. 縦連は同時押しを含めて数え, トリルと階段は単押しが間隔を空けずに続く区間で数える.
+    let jacks = rows
+        .iter()
+        .tuple_windows()
+        .filter(|((prev_time, _), (time, _))| time - prev_time <= PATTERN_INTERVAL)
+        .map(|((_, prev_keys), (_, keys))| {
+            keys.iter().filter(|key| prev_keys.contains(key)).count() as u32
+        })
+        .sum::<u32>();
+    let mut trills = 0;
+    let mut stairs = 0;
+    let mut segment = Vec::<i32>::new();
+    let mut prev_time = f64::NEG_INFINITY;
+    for (time, keys) in rows.iter().chain(std::iter::once(&(f64::INFINITY, vec![]))) {
+        if keys.len() != 1 || time - prev_time > PATTERN_INTERVAL {
+            trills += count_runs(&segment, MIN_TRILL_LENGTH, is_trill);
+            stairs += count_runs(&segment, MIN_STAIR_LENGTH, is_stair);
+            segment.clear();
+        }
+        if keys.len() == 1 {
+            segment.push(keys[0]);
+        }
+        prev_time = *time;
+    }
+
+    // 瞬間的な密度と持続的な密度を半分ずつ取り, 配置の多さで上乗せする
+    let sustained_num = ((nps_curve.len() as f64 * SUSTAINED_RATIO).ceil() as usize).max(1);
+    let sustained_nps = nps_curve
+        .iter()
+        .sorted_by(|a, b| b.cmp(a))
+        .take(sustained_num)
+        .sum::<u32>() as f64
+        / sustained_num as f64;
+    let pattern_bonus =
+        (jacks as f64 * JACK_WEIGHT + trills as f64 * TRILL_WEIGHT + stairs as f64 * STAIR_WEIGHT)
+            / note_count as f64;
+    let rating = (peak_nps as f64 + sustained_nps) / 2.0 * (1.0 + pattern_bonus) * RATING_SCALE;
+
+    ChartAnalysis {
+        note_count,
+        nps_curve,
+        peak_nps,
+        average_nps,
+        jacks,
+        trills,
+        stairs,
+        rating: (rating * 10.0).round() / 10.0,
+    }
+}
+
+/// 譜面データを時刻付きのノーツ列に直して分析する
+pub fn analyze_chart(config: &SongConfig) -> ChartAnalysis {
+    // 出現時刻は使わないので速さは何でもよい
+    let notes = to_notes_info_from_notes_spawn(
+        config.notes.clone(),
+        1.0,
+        config.initial_bpm,
+        config.initial_beat,
+        config.offset / 1000.0,
+    );
+    analyze_notes(&notes)
+}
+
+#[test]
+fn analysis_test() {
+    use super::{beat_position::BeatPosition, note::NoteType};
+
+    let note = |key: i32, target_time: f64| NoteInfo {
+        note_type: NoteType::Normal { key },
+        bar: 0,
+        beat: BeatPosition::ZERO,
+        spawn_time: 0.0,
+        target_time,
+        end_time: target_time,
+        tick_times: vec![],
+        bpm: 120.0,
+        sound: None,
+    };
+    // 0.25秒間隔で, トリル(0,1,0,1)から続けて階段(0,1,2,3), 縦連(3,3). 最後に同時押し.
+    let mut notes = [0, 1, 0, 1, 2, 3, 3]
+        .into_iter()
+        .enumerate()
+        .map(|(i, key)| note(key, i as f64 * 0.25))
+        .collect_vec();
+    notes.push(note(0, 2.0));
+    notes.push(note(1, 2.0));
+    notes.push(NoteInfo {
+        note_type: NoteType::BarLine,
+        ..note(0, 0.0)
+    });
+
+    let analysis = analyze_notes(&notes);
+    assert_eq!(analysis.note_count, 9);
+    assert_eq!(analysis.nps_curve, vec![4, 3, 2]);
+    assert_eq!(analysis.peak_nps, 4);
+    assert_eq!(analysis.jacks, 1);
+    assert_eq!(analysis.trills, 1);
+    assert_eq!(analysis.stairs, 1);
+    assert!(analysis.rating > 0.0);
+
+    assert_eq!(analyze_notes(&[]).rating, 0.0);
+}
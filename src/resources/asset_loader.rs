@@ -0,0 +1,112 @@
+//! 譜面と曲一覧, 判定幅の設定をBevyのアセットとして読み込むためのローダー.
+//! アセットサーバーを通すことでファイルの変更を検知して再読み込みできる.
+//! yamlとして解釈できない場合もロード自体は成功させ, エラー画面で位置を表示できるように結果を保持する.
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+
+use super::{
+    judge::JudgeConfigParser,
+    load_error::YamlError,
+    song::{SongConfig, SongConfigParser},
+    song_list::SongDataParser,
+};
+
+/// 曲一覧ファイルのパス（assetsからの相対パス）.
+/// 譜面と区別するために拡張子を`songlist.yaml`としている.
+pub const SONG_LIST_PATH: &str = "songs/all_song_data.songlist.yaml";
+
+/// 譜面ファイルのアセット
+#[derive(TypeUuid, Debug)]
+#[uuid = "8f3c2a5e-6d1b-4b7e-9a40-2c5d7e1f9b63"]
+pub struct ChartAsset(pub Result<SongConfig, YamlError>);
+
+/// 曲一覧ファイルのアセット
+#[derive(TypeUuid, Debug)]
+#[uuid = "1d6e9b74-3a2f-4c85-b0e1-7f4a9c2d5e18"]
+pub struct SongListAsset(pub Result<Vec<SongDataParser>, YamlError>);
+
+/// 判定幅の設定ファイルのアセット
+#[derive(TypeUuid, Debug)]
+#[uuid = "5b2e8d14-9c7a-4f36-a1d8-3e6f0b9c4a27"]
+pub struct JudgeConfigAsset(pub Result<JudgeConfigParser, YamlError>);
+
+#[derive(Default)]
+pub struct ChartAssetLoader;
+impl AssetLoader for ChartAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let parsed = serde_yaml::from_slice::<SongConfigParser>(bytes)
+                .map(SongConfig::from)
+                .map_err(YamlError::from);
+            load_context.set_default_asset(LoadedAsset::new(ChartAsset(parsed)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["yaml"]
+    }
+}
+
+#[derive(Default)]
+pub struct SongListAssetLoader;
+impl AssetLoader for SongListAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let parsed =
+                serde_yaml::from_slice::<Vec<SongDataParser>>(bytes).map_err(YamlError::from);
+            load_context.set_default_asset(LoadedAsset::new(SongListAsset(parsed)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["songlist.yaml"]
+    }
+}
+
+#[derive(Default)]
+pub struct JudgeConfigAssetLoader;
+impl AssetLoader for JudgeConfigAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let parsed =
+                serde_yaml::from_slice::<JudgeConfigParser>(bytes).map_err(YamlError::from);
+            load_context.set_default_asset(LoadedAsset::new(JudgeConfigAsset(parsed)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["judge.yaml"]
+    }
+}
+
+/// 譜面アセットのハンドル. ゲーム中に保持しておくことで変更を検知できる.
+#[derive(Resource, Deref)]
+pub struct ChartHandle(pub Handle<ChartAsset>);
+
+/// 曲一覧アセットのハンドル. 選曲画面で保持しておくことで変更を検知できる.
+#[derive(Resource, Deref)]
+pub struct SongListHandle(pub Handle<SongListAsset>);
+
+/// 判定幅の設定アセットのハンドル. ゲームの読み込み中のみ保持する.
+#[derive(Resource, Deref)]
+pub struct JudgeConfigHandle(pub Handle<JudgeConfigAsset>);
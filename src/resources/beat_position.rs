@@ -0,0 +1,271 @@
+//! 小節内の拍位置を分数で表す.
+//! 浮動小数点数では3連符（1/3, 2/3）が正確に表せず, 並べ替えや重複の判定でずれが起きるため,
+//! 譜面上の位置は分数で持ち, 秒への変換は読み込み時に一度だけ行う.
+
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Sub},
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// 小数で書かれた拍位置を分数に直すときに試す分母の上限
+const MAX_GUESS_DENOMINATOR: u64 = 192;
+/// 小数を分数に直すときに同じとみなす拍の差. 浮動小数点数の丸め誤差だけを吸収する.
+const GUESS_TOLERANCE: f64 = 1e-9;
+/// 分数で表せない小数をそのまま持つときの最大の桁数
+const MAX_DECIMAL_DIGITS: u32 = 9;
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// 拍位置. 常に約分された状態で持つ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BeatPosition {
+    numerator: u64,
+    denominator: u64,
+}
+
+impl BeatPosition {
+    pub const ZERO: Self = Self {
+        numerator: 0,
+        denominator: 1,
+    };
+
+    pub fn new(numerator: u64, denominator: u64) -> Self {
+        assert!(denominator != 0, "denominator of beat must not be 0");
+        let g = gcd(numerator, denominator).max(1);
+        Self {
+            numerator: numerator / g,
+            denominator: denominator / g,
+        }
+    }
+
+    pub fn from_int(beat: u32) -> Self {
+        Self::new(beat as u64, 1)
+    }
+
+    /// 小数を分数に直す. 3連符の`1.0 / 3.0`のように小さい分母の分数と丸め誤差しか違わないものはその分数にし,
+    /// それ以外は書かれた小数をそのまま10の累乗を分母とする分数で持つ（`0.3333`は1/3にならない）.
+    /// 負の数やNaNは拍位置にならない.
+    pub fn from_f64(beat: f64) -> Option<Self> {
+        if !beat.is_finite() || beat < 0.0 {
+            return None;
+        }
+        Some(Self::guess(beat, GUESS_TOLERANCE).unwrap_or_else(|| {
+            let denominator = (0..MAX_DECIMAL_DIGITS)
+                .map(|digits| 10u64.pow(digits))
+                .find(|&d| ((beat * d as f64).round() / d as f64 - beat).abs() < GUESS_TOLERANCE)
+                .unwrap_or(10u64.pow(MAX_DECIMAL_DIGITS));
+            Self::new((beat * denominator as f64).round() as u64, denominator)
+        }))
+    }
+
+    /// 単精度の小数を分数に直す. 単精度の丸め誤差を許して小さい分母の分数を探し,
+    /// 見つからなければ単精度で表示される小数として`from_f64`と同様に扱う.
+    pub fn from_f32(beat: f32) -> Option<Self> {
+        if !beat.is_finite() || beat < 0.0 {
+            return None;
+        }
+        let tolerance = f32::EPSILON as f64 * (beat as f64).max(1.0);
+        Self::guess(beat as f64, tolerance).or_else(|| {
+            beat.to_string()
+                .parse::<f64>()
+                .ok()
+                .and_then(Self::from_f64)
+        })
+    }
+
+    /// 差が`tolerance`未満の分数を小さい分母から探す
+    fn guess(beat: f64, tolerance: f64) -> Option<Self> {
+        (1..=MAX_GUESS_DENOMINATOR).find_map(|denominator| {
+            let numerator = (beat * denominator as f64).round();
+            ((numerator / denominator as f64 - beat).abs() < tolerance)
+                .then(|| Self::new(numerator as u64, denominator))
+        })
+    }
+
+    /// 一拍を`division`等分したグリッドのうち最も近い位置に寄せる
+    pub fn snap(beat: f64, division: u32) -> Self {
+        let numerator = (beat.max(0.0) * division as f64).round() as u64;
+        Self::new(numerator, division as u64)
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// 差を求める. 負になる場合はNone.
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        let lhs_num = self.numerator * rhs.denominator;
+        let rhs_num = rhs.numerator * self.denominator;
+        (lhs_num >= rhs_num)
+            .then(|| Self::new(lhs_num - rhs_num, self.denominator * rhs.denominator))
+    }
+
+    /// 有限小数で表せるか（分母が2と5のみを因数に持つか）
+    fn is_terminating(&self) -> bool {
+        let mut d = self.denominator;
+        for p in [2, 5] {
+            while d.is_multiple_of(p) {
+                d /= p;
+            }
+        }
+        d == 1
+    }
+}
+
+impl Default for BeatPosition {
+    fn default() -> Self {
+        Self::ZERO
+    }
+}
+
+impl Ord for BeatPosition {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.numerator as u128 * other.denominator as u128)
+            .cmp(&(other.numerator as u128 * self.denominator as u128))
+    }
+}
+impl PartialOrd for BeatPosition {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for BeatPosition {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(
+            self.numerator * rhs.denominator + rhs.numerator * self.denominator,
+            self.denominator * rhs.denominator,
+        )
+    }
+}
+impl Sub for BeatPosition {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        self.checked_sub(rhs)
+            .expect("beat position must not be negative")
+    }
+}
+
+impl fmt::Display for BeatPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+/// 有限小数で表せるものは数値として, そうでないものは`"1/3"`のような文字列として書き出す
+impl Serialize for BeatPosition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_terminating() {
+            serializer.serialize_f64(self.as_f64())
+        } else {
+            serializer.serialize_str(&self.to_string())
+        }
+    }
+}
+
+/// 数値（`0.5`）と分数の文字列（`"1/3"`, `1/3`）のどちらでも読めるようにする
+impl<'de> Deserialize<'de> for BeatPosition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BeatVisitor;
+        impl<'de> de::Visitor<'de> for BeatVisitor {
+            type Value = BeatPosition;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a non-negative number or a fraction like \"1/3\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(BeatPosition::new(v, 1))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u64::try_from(v)
+                    .map(|v| BeatPosition::new(v, 1))
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                BeatPosition::from_f64(v)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Float(v), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                let invalid = || E::invalid_value(de::Unexpected::Str(v), &self);
+                match v.split_once('/') {
+                    Some((numerator, denominator)) => {
+                        let numerator = numerator.trim().parse::<u64>().map_err(|_| invalid())?;
+                        let denominator =
+                            denominator.trim().parse::<u64>().map_err(|_| invalid())?;
+                        if denominator == 0 {
+                            return Err(invalid());
+                        }
+                        Ok(BeatPosition::new(numerator, denominator))
+                    }
+                    None => v
+                        .trim()
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(BeatPosition::from_f64)
+                        .ok_or_else(invalid),
+                }
+            }
+        }
+        deserializer.deserialize_any(BeatVisitor)
+    }
+}
+
+#[test]
+fn beat_position_test() {
+    let third = BeatPosition::new(1, 3);
+    assert_eq!(third + third + third, BeatPosition::from_int(1));
+    assert_eq!(BeatPosition::from_f64(1.0 / 3.0), Some(third));
+    assert_eq!(BeatPosition::from_f32(1.0 / 3.0), Some(third));
+    // 小さい分母の分数に近いだけの小数は書かれた値のまま持つ
+    assert_eq!(
+        BeatPosition::from_f64(0.123),
+        Some(BeatPosition::new(123, 1000))
+    );
+    assert_eq!(
+        BeatPosition::from_f64(0.3333),
+        Some(BeatPosition::new(3333, 10000))
+    );
+    assert_eq!(BeatPosition::from_f64(f64::NAN), None);
+    assert!(BeatPosition::new(2, 3) > BeatPosition::new(1, 2));
+    assert_eq!(BeatPosition::snap(0.26, 4), BeatPosition::new(1, 4));
+
+    let parsed =
+        serde_yaml::from_str::<Vec<BeatPosition>>("[0, 1.5, \"1/3\", 2/6, 0.123]").unwrap();
+    assert_eq!(
+        parsed,
+        vec![
+            BeatPosition::ZERO,
+            BeatPosition::new(3, 2),
+            third,
+            third,
+            BeatPosition::new(123, 1000)
+        ]
+    );
+    assert!(serde_yaml::from_str::<BeatPosition>(".nan").is_err());
+    assert!(serde_yaml::from_str::<BeatPosition>("-1").is_err());
+    // 書き出して読み直すと同じになる
+    let yaml = serde_yaml::to_string(&parsed).unwrap();
+    assert!(yaml.contains("0.123"));
+    assert_eq!(
+        serde_yaml::from_str::<Vec<BeatPosition>>(&yaml).unwrap(),
+        parsed
+    );
+}
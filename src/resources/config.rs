@@ -1,6 +1,35 @@
 //! ユーザーが関与する・しないを問わず, ゲーム中の設定に関するリソースを定義する.
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// プレイヤーごとの設定を保存するファイルのパス（実行ディレクトリからの相対パス）
+pub const USER_CONFIG_PATH: &str = "user_config.yaml";
+
+/// プレイヤーごとの設定ファイルの内容. 起動時に読み込み, 選曲画面で変更したときに書き出す.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserConfigParser {
+    /// 全体オフセット（ミリ秒）
+    #[serde(default)]
+    pub global_offset: f64,
+}
+impl UserConfigParser {
+    /// 設定ファイルを読み込む. ファイルがない場合や読めない場合は初期設定とする.
+    pub fn load(path: &str) -> Self {
+        let Ok(source) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        serde_yaml::from_str(&source).unwrap_or_else(|e| {
+            warn!("{}: {}. default settings are used", path, e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let data_str = serde_yaml::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, data_str).map_err(|e| format!("{}: {}", path, e))
+    }
+}
 
 /// いわゆるハイスピ. BASE_SPEED定数があるので倍率で指定.
 #[derive(Resource, Deref, DerefMut)]
@@ -12,6 +41,22 @@ impl Default for NoteSpeed {
     }
 }
 
+/// プレイヤーごとの全体オフセット（ミリ秒）. 音声出力の遅延を補正するために使う.
+/// 正の値にすると曲に対して譜面が遅れて流れる.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct GlobalOffset(pub f64);
+impl GlobalOffset {
+    /// 秒単位で取得する
+    pub fn as_secs(&self) -> f64 {
+        self.0 / 1000.0
+    }
+}
+impl From<&UserConfigParser> for GlobalOffset {
+    fn from(config: &UserConfigParser) -> Self {
+        Self(config.global_offset)
+    }
+}
+
 /// bpmを表すリソース
 #[derive(Resource, Deref, DerefMut)]
 pub struct Bpm(pub f32);
@@ -20,6 +65,10 @@ pub struct Bpm(pub f32);
 #[derive(Resource, Deref, DerefMut)]
 pub struct Beat(pub u32);
 
+/// 譜面の鍵盤数を表すリソース
+#[derive(Resource, Deref, DerefMut, Clone, Copy)]
+pub struct LaneNum(pub u8);
+
 #[derive(Clone, Copy, Resource, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum GameDifficulty {
     Normal,
@@ -42,3 +91,75 @@ impl std::fmt::Display for GameDifficulty {
         write!(f, "{:?}", self)
     }
 }
+
+/// ライフゲージの種類. 選曲画面で選ぶ.
+#[derive(Clone, Copy, Resource, Debug, PartialEq, Eq, Default)]
+pub enum GaugeType {
+    /// 回復しやすく, ミスによる減少も小さい
+    #[default]
+    Normal,
+    /// 回復しにくく, ミスによる減少が大きい
+    Hard,
+    /// 回復せず, ミスのたびに少しずつ減っていく
+    Survival,
+}
+impl GaugeType {
+    /// Perfectでのゲージの回復量. Okではこの半分になる.
+    pub fn recovery(&self) -> f64 {
+        match self {
+            GaugeType::Normal => 0.01,
+            GaugeType::Hard => 0.005,
+            GaugeType::Survival => 0.0,
+        }
+    }
+
+    /// ミスや地雷でのゲージの減少量
+    pub fn damage(&self) -> f64 {
+        match self {
+            GaugeType::Normal => 0.05,
+            GaugeType::Hard => 0.1,
+            GaugeType::Survival => 0.04,
+        }
+    }
+
+    pub fn get_color(&self) -> Color {
+        match self {
+            GaugeType::Normal => Color::LIME_GREEN,
+            GaugeType::Hard => Color::RED,
+            GaugeType::Survival => Color::CYAN,
+        }
+    }
+}
+impl std::fmt::Display for GaugeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// ゲージが空になっても途中で終了せずに最後まで遊べるようにするオプション
+#[derive(Resource, Deref, DerefMut, Default, Clone, Copy)]
+pub struct NoFail(pub bool);
+
+/// ゲーム中に判定線の下へ叩いた時刻の誤差の目盛り（ヒットエラーバー）を表示するか
+#[derive(Resource, Deref, DerefMut, Clone, Copy)]
+pub struct HitErrorBar(pub bool);
+
+impl Default for HitErrorBar {
+    fn default() -> Self {
+        HitErrorBar(true)
+    }
+}
+
+#[test]
+fn user_config_test() {
+    let path = std::env::temp_dir().join("rhythm_2_user_config_test.yaml");
+    let path = path.to_str().unwrap();
+    let config = UserConfigParser {
+        global_offset: -35.0,
+    };
+    config.save(path).unwrap();
+    assert_eq!(UserConfigParser::load(path), config);
+    std::fs::remove_file(path).unwrap();
+    // ファイルがなければ初期設定になる
+    assert_eq!(UserConfigParser::load(path), UserConfigParser::default());
+}
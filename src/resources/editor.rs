@@ -2,11 +2,14 @@ use std::collections::VecDeque;
 
 use bevy::prelude::*;
 
+use super::beat_position::BeatPosition;
+
 #[derive(Clone, Debug)]
 pub struct EditNote {
     pub key: i32,
     pub bar: u32,
-    pub beat: f64,
+    /// グリッドに寄せた拍位置
+    pub beat: BeatPosition,
     // pub time_after_start: f64,
 }
 
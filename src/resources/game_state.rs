@@ -4,6 +4,10 @@ use bevy::prelude::*;
 #[derive(Resource)]
 pub struct NextAppState(pub AppState);
 
+/// 存在していれば, 曲一覧や譜面のアセットの読み込みを待ってから次のシーンのリソースを用意することを表す.
+#[derive(Resource)]
+pub struct WaitingSceneData;
+
 /// エンティティ保存用リソース.
 #[derive(Resource)]
 pub struct ExistingEntities(pub Vec<Entity>);
@@ -15,8 +15,10 @@ pub struct GameAssetsHandles {
     // フォント
     pub main_font: Handle<Font>,
 
-    // 曲
-    pub music: Handle<AudioSource>,
+    // 曲. キー音だけで構成される譜面では曲のファイルがない.
+    pub music: Option<Handle<AudioSource>>,
+    // キー音. 譜面の`keysounds`と同じ順に並ぶ.
+    pub keysounds: Vec<Handle<AudioSource>>,
 
     // 色
     pub color_material_red: Handle<ColorMaterial>,
@@ -25,11 +27,13 @@ pub struct GameAssetsHandles {
     pub color_material_white: Handle<ColorMaterial>,
     pub color_material_white_trans: Handle<ColorMaterial>,
     pub color_material_trans: Handle<ColorMaterial>,
-    // 4鍵それぞれで色を用意するとエフェクトとして使える
+    pub color_material_mine: Handle<ColorMaterial>,
+    // 鍵盤それぞれで色を用意するとエフェクトとして使える
     pub color_material_lane_background: Vec<Handle<ColorMaterial>>,
 
     // メッシュ
     pub note: Handle<Mesh>,
+    pub mine_note: Handle<Mesh>,
     pub bar_note: Handle<Mesh>,
     pub judge_line: Handle<Mesh>,
     pub lane_line: Handle<Mesh>,
@@ -49,6 +53,8 @@ impl GameAssetsHandles {
     /// アセットをロードしてハンドルとして保持しておく
     pub fn new(
         music_filename: String,
+        keysounds: &[String],
+        lane_num: u8,
         server: &Res<AssetServer>,
         texture_atlas: &mut ResMut<Assets<TextureAtlas>>,
         color_material: &mut ResMut<Assets<ColorMaterial>>,
@@ -56,21 +62,36 @@ impl GameAssetsHandles {
     ) -> Self {
         let numbers = server.load("images/numbers.png");
         let note_shape = shape::Quad::new(Vec2::new(100.0, 8.0));
-        let bar_note_shape = shape::Quad::new(Vec2::new(400.0, 4.0));
+        // 地雷は通常ノーツと見分けられるよう小さな正方形にする
+        let mine_note_shape = shape::Quad::new(Vec2::new(24.0, 24.0));
+        let bar_note_shape = shape::Quad::new(Vec2::new(LANE_WIDTH * lane_num as f32, 4.0));
         let judge_line_shape = shape::Quad::new(Vec2::new(700.0, 6.0));
         let lane_line_shape = shape::Quad::new(Vec2::new(4.0, 500.0));
         let lane_background_shape = shape::Quad::new(Vec2::new(LANE_WIDTH, 500.0));
 
-        let color_material_lane_background = vec![
-            color_material.add(ColorMaterial::from(Color::CRIMSON)),
-            color_material.add(ColorMaterial::from(Color::SEA_GREEN)),
-            color_material.add(ColorMaterial::from(Color::SEA_GREEN)),
-            color_material.add(ColorMaterial::from(Color::CRIMSON)),
-        ];
+        // 外側から赤, 緑を交互に並べ, 奇数鍵の中央は金色にする
+        let color_material_lane_background = (0..lane_num)
+            .map(|i| {
+                let distance = i.min(lane_num - 1 - i);
+                let color = if lane_num % 2 == 1 && i == lane_num / 2 {
+                    Color::GOLD
+                } else if distance % 2 == 0 {
+                    Color::CRIMSON
+                } else {
+                    Color::SEA_GREEN
+                };
+                color_material.add(ColorMaterial::from(color))
+            })
+            .collect();
         Self {
             main_font: server.load("fonts/FiraSans-Bold.ttf"),
 
-            music: server.load(format!("songs/{}", music_filename)),
+            music: (!music_filename.is_empty())
+                .then(|| server.load(format!("songs/{}", music_filename))),
+            keysounds: keysounds
+                .iter()
+                .map(|filename| server.load(format!("songs/{}", filename)))
+                .collect(),
 
             color_material_red: color_material.add(ColorMaterial::from(Color::RED)),
             color_material_blue: color_material.add(ColorMaterial::from(Color::BLUE)),
@@ -79,9 +100,11 @@ impl GameAssetsHandles {
             color_material_white_trans: color_material
                 .add(ColorMaterial::from(Color::rgba(1.0, 1.0, 1.0, 0.5))),
             color_material_trans: color_material.add(ColorMaterial::from(Color::NONE)),
+            color_material_mine: color_material.add(ColorMaterial::from(Color::PURPLE)),
             color_material_lane_background,
 
             note: meshes.add(Mesh::from(note_shape)),
+            mine_note: meshes.add(Mesh::from(mine_note_shape)),
             bar_note: meshes.add(bar_note_shape.into()),
             judge_line: meshes.add(Mesh::from(judge_line_shape)),
             lane_line: meshes.add(Mesh::from(lane_line_shape)),
@@ -104,7 +127,8 @@ impl GameAssetsHandles {
         color_material: &mut ResMut<Assets<ColorMaterial>>,
         note_type: &NoteType,
         speed: f32,
-        bpm: f32,
+        hold_time: f64,
+        lane_num: u8,
         edit_mode: bool,
     ) -> ColorMesh2dBundle {
         // エディット時は下から出現するため出現位置を調整したものを用意する
@@ -117,7 +141,7 @@ impl GameAssetsHandles {
         match note_type {
             NoteType::Normal { key } => {
                 let transform = Transform {
-                    translation: Vec3::new(KeyLane::x_coord_from_num(*key), spawn_y, 1.0),
+                    translation: Vec3::new(KeyLane::x_coord_from_num(*key, lane_num), spawn_y, 1.0),
                     ..Default::default()
                 };
                 ColorMesh2dBundle {
@@ -141,7 +165,7 @@ impl GameAssetsHandles {
             }
             NoteType::AdLib { key } => {
                 let transform = Transform {
-                    translation: Vec3::new(KeyLane::x_coord_from_num(*key), spawn_y, 1.0),
+                    translation: Vec3::new(KeyLane::x_coord_from_num(*key, lane_num), spawn_y, 1.0),
                     ..Default::default()
                 };
                 ColorMesh2dBundle {
@@ -151,12 +175,21 @@ impl GameAssetsHandles {
                     ..Default::default()
                 }
             }
-            NoteType::Long { key, length, id: _ } => {
-                // 拍数 * 移動量(px/秒) / (拍/秒) で長さを計算
-                let note_height = length * speed * BASIC_NOTE_SPEED / bpm * 60.0;
+            NoteType::Long {
+                key,
+                length: _,
+                id: _,
+            }
+            | NoteType::Roll {
+                key,
+                length: _,
+                id: _,
+            } => {
+                // 始点から終点までの時間(秒) * 移動量(px/秒) で長さを計算
+                let note_height = hold_time as f32 * speed * BASIC_NOTE_SPEED;
                 let transform = Transform {
                     translation: Vec3::new(
-                        KeyLane::x_coord_from_num(*key),
+                        KeyLane::x_coord_from_num(*key, lane_num),
                         spawn_y + note_height / 2.0,
                         0.9,
                     ),
@@ -164,7 +197,13 @@ impl GameAssetsHandles {
                     scale: Vec3::new(1.0, note_height / 8.0, 1.0),
                     ..Default::default()
                 };
-                let new_color = color_material.add(Color::rgba(1.0, 1.0, 1.0, 0.7).into());
+                // ロールノーツは橙色にして区別する
+                let color = if matches!(note_type, NoteType::Roll { .. }) {
+                    Color::rgba(1.0, 0.6, 0.0, 0.7)
+                } else {
+                    Color::rgba(1.0, 1.0, 1.0, 0.7)
+                };
+                let new_color = color_material.add(color.into());
                 ColorMesh2dBundle {
                     mesh: self.note.clone().into(),
                     material: new_color,
@@ -172,20 +211,51 @@ impl GameAssetsHandles {
                     ..Default::default()
                 }
             }
+            NoteType::Mine { key } => {
+                // 菱形に見えるよう45度回す
+                let transform = Transform {
+                    translation: Vec3::new(KeyLane::x_coord_from_num(*key, lane_num), spawn_y, 1.0),
+                    rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_4),
+                    ..Default::default()
+                };
+                ColorMesh2dBundle {
+                    mesh: self.mine_note.clone().into(),
+                    material: self.color_material_mine.clone(),
+                    transform,
+                    ..Default::default()
+                }
+            }
+            NoteType::BpmChange { bpm: _ }
+            | NoteType::BeatChange { beat: _ }
+            | NoteType::KeySound { sound: _ } => {
+                // 見えないノーツとして出現させる
+                let transform = Transform {
+                    translation: Vec3::new(0.0, spawn_y, 0.5),
+                    ..Default::default()
+                };
+                ColorMesh2dBundle {
+                    mesh: self.bar_note.clone().into(),
+                    material: self.color_material_trans.clone(),
+                    transform,
+                    ..Default::default()
+                }
+            }
         }
     }
 }
 impl AssetHandles for GameAssetsHandles {
     fn to_untyped_vec(&self) -> Vec<HandleUntyped> {
         // let assets_loading_vec = vec![];
-        vec![
+        let mut handles = vec![
             // フォント
             self.main_font.clone_untyped(),
-            // 曲
-            self.music.clone_untyped(),
             // 画像類
             self.numbers.clone_untyped(),
             self.background.clone_untyped(),
-        ]
+        ];
+        // 曲
+        handles.extend(self.music.iter().map(|music| music.clone_untyped()));
+        handles.extend(self.keysounds.iter().map(|sound| sound.clone_untyped()));
+        handles
     }
 }
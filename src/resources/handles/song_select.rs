@@ -1,6 +1,6 @@
 use bevy::{prelude::*, utils::HashMap};
 
-use crate::resources::song_list::SongData;
+use crate::resources::{asset_loader::ChartAsset, song_list::SongData};
 
 use super::AssetHandles;
 
@@ -17,6 +17,10 @@ pub struct SongSelectAssetHandles {
     pub thumb_mesh: Handle<Mesh>,
     // サムネ用マテリアル
     pub thumb_img: HashMap<String, Handle<Image>>,
+
+    /// BPMや尺を表示するための譜面. 譜面ファイル名で引く.
+    /// 読めない譜面があっても選曲画面は開けるように, 読み込みの完了は待たない.
+    pub charts: HashMap<String, Handle<ChartAsset>>,
 }
 
 impl SongSelectAssetHandles {
@@ -33,6 +37,14 @@ impl SongSelectAssetHandles {
             let img = server.load(format!("images/thumb/{}", data.thumbnail));
             thumb_img.insert(data.name.clone(), img.clone());
         }
+        let charts = song_data
+            .iter()
+            .flat_map(|data| data.charts.iter())
+            .map(|chart| {
+                let handle = server.load(format!("songs/{}", chart.config_file_name));
+                (chart.config_file_name.clone(), handle)
+            })
+            .collect::<HashMap<_, _>>();
 
         Self {
             main_font: server.load("fonts/FiraSans-Bold.ttf"),
@@ -41,6 +53,7 @@ impl SongSelectAssetHandles {
 
             thumb_mesh: meshes.add(thumb_shape.into()),
             thumb_img,
+            charts,
         }
     }
 }
@@ -0,0 +1,537 @@
+//! BMSの譜面ファイル（.bms, .bme）を読み込む.
+//! BMSでは`#小節番号チャンネル:オブジェクト列`の形式で, 小節内を等分した位置にオブジェクト（2桁の36進数）を置く.
+//! BGMチャンネルや各ノーツのキー音はキー音イベントとして残し, 曲のファイルなしで再生できるようにする.
+
+use std::collections::{BTreeMap, HashMap};
+
+use itertools::Itertools;
+
+use super::{note_at, sorted_notes, BpmTimeline, ImportError, ImportedChart, MeterTimeline};
+use crate::{
+    components::note::KeyLane,
+    resources::{
+        note::{NoteSpawn, NoteType},
+        song::{SongConfig, SongConfigParser},
+    },
+};
+
+/// BMSの標準の一小節の拍数
+const BEAT_PAR_BAR: u32 = 4;
+/// 最後のキー音から曲の終わりまでの余白（秒）. キー音の長さは分からないので長めに取る.
+const LENGTH_MARGIN: f64 = 4.0;
+/// 1P側の鍵盤のチャンネルを左から並べたもの. 16はスクラッチ.
+const PLAYER_CHANNELS: [&str; 8] = ["16", "11", "12", "13", "14", "15", "18", "19"];
+const SCRATCH_CHANNEL: &str = "16";
+/// BGM, BPM変更, 拡張BPM変更のチャンネル
+const BGM_CHANNEL: &str = "01";
+const BPM_CHANNEL: &str = "03";
+const EXTENDED_BPM_CHANNEL: &str = "08";
+const BAR_LENGTH_CHANNEL: &str = "02";
+const STOP_CHANNEL: &str = "09";
+/// #DIFFICULTYの値に対応する難易度名
+const DIFFICULTY_NAMES: [&str; 5] = ["Beginner", "Normal", "Hyper", "Another", "Insane"];
+
+/// 36進数2桁のオブジェクト番号を読む
+fn parse_object_id(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 36).ok()
+}
+
+/// 小節番号, チャンネル, 小節内の位置（0以上1未満）, オブジェクト番号
+struct BmsObject {
+    bar: u32,
+    channel: String,
+    position: f64,
+    id: u32,
+}
+
+/// ファイルの内容. ヘッダーはキーを大文字にして持つ.
+struct BmsFile {
+    headers: HashMap<String, String>,
+    /// 小節ごとの長さ（4拍に対する倍率）
+    bar_lengths: BTreeMap<u32, f64>,
+    objects: Vec<BmsObject>,
+}
+
+fn parse_file(source: &str, warnings: &mut Vec<String>) -> Result<BmsFile, ImportError> {
+    let mut file = BmsFile {
+        headers: HashMap::new(),
+        bar_lengths: BTreeMap::new(),
+        objects: vec![],
+    };
+    // #IFのネスト. 乱数は使わず, 常に#RANDOMの結果が1であったものとして扱う.
+    let mut skipping = vec![];
+    for line in source.lines().map(str::trim) {
+        let Some(line) = line.strip_prefix('#') else {
+            continue;
+        };
+        let (command, value) = line
+            .split_once(|c: char| c == ':' || c.is_whitespace())
+            .unwrap_or((line, ""));
+        let command = command.to_uppercase();
+        match command.as_str() {
+            "RANDOM" | "SETRANDOM" => {
+                warnings.push("#RANDOM is not supported; branch 1 is always used".to_string());
+                continue;
+            }
+            "IF" => {
+                skipping.push(value.trim() != "1");
+                continue;
+            }
+            "ENDIF" | "END" => {
+                skipping.pop();
+                continue;
+            }
+            _ => {}
+        }
+        if skipping.iter().any(|skip| *skip) {
+            continue;
+        }
+
+        // 小節番号3桁とチャンネル2桁ならオブジェクト列
+        let is_channel = command.len() == 5
+            && command.chars().all(|c| c.is_ascii_alphanumeric())
+            && command[..3].chars().all(|c| c.is_ascii_digit());
+        if !is_channel {
+            file.headers.insert(command, value.trim().to_string());
+            continue;
+        }
+        let bar = command[..3].parse::<u32>().unwrap();
+        let channel = command[3..].to_string();
+        let data = value.trim();
+        if channel == BAR_LENGTH_CHANNEL {
+            let length = data
+                .parse::<f64>()
+                .map_err(|_| ImportError::Syntax(format!("invalid bar length: {}", data)))?;
+            file.bar_lengths.insert(bar, length);
+            continue;
+        }
+        let chars = data.chars().collect_vec();
+        let num = chars.len() / 2;
+        for (i, pair) in chars.chunks_exact(2).enumerate() {
+            let pair = pair.iter().collect::<String>();
+            let id = parse_object_id(&pair)
+                .ok_or_else(|| ImportError::Syntax(format!("invalid object: {}", pair)))?;
+            if id == 0 {
+                continue;
+            }
+            file.objects.push(BmsObject {
+                bar,
+                channel: channel.clone(),
+                position: i as f64 / num as f64,
+                id,
+            });
+        }
+    }
+    Ok(file)
+}
+
+/// 小節ごとの長さから, 小節の頭の通算拍数を求める
+struct BarHeads(Vec<f64>);
+impl BarHeads {
+    fn new(bar_lengths: &BTreeMap<u32, f64>, bar_num: u32) -> Self {
+        let mut heads = vec![0.0];
+        for bar in 0..bar_num {
+            let length = bar_lengths.get(&bar).copied().unwrap_or(1.0);
+            heads.push(heads[bar as usize] + length * BEAT_PAR_BAR as f64);
+        }
+        Self(heads)
+    }
+
+    fn beat_at(&self, bar: u32, position: f64) -> f64 {
+        let head = self.0[bar as usize];
+        head + (self.0[bar as usize + 1] - head) * position
+    }
+}
+
+/// 小節の長さから拍子を決める. 4拍の整数倍にならない長さは近い拍数に丸める.
+fn build_meters(
+    file: &BmsFile,
+    heads: &BarHeads,
+    bar_num: u32,
+    warnings: &mut Vec<String>,
+) -> MeterTimeline {
+    let mut meters = MeterTimeline::new(BEAT_PAR_BAR);
+    for bar in 0..bar_num {
+        let length = file.bar_lengths.get(&bar).copied().unwrap_or(1.0);
+        let beats = length * BEAT_PAR_BAR as f64;
+        let rounded = beats.round().max(1.0);
+        if (beats - rounded).abs() > 1e-6 {
+            warnings.push(format!(
+                "bar {} has {} beats, which is rounded to {}",
+                bar, beats, rounded
+            ));
+        }
+        meters.change(heads.0[bar as usize], rounded as u32);
+    }
+    meters
+}
+
+/// ロングノーツのチャンネル（5x）なら対応する通常のチャンネル（1x）を返す
+fn long_note_channel(channel: &str) -> Option<String> {
+    channel.strip_prefix('5').map(|c| format!("1{}", c))
+}
+
+/// 地雷のチャンネル（Dx）なら対応する通常のチャンネル（1x）を返す
+fn mine_channel(channel: &str) -> Option<String> {
+    channel.strip_prefix('D').map(|c| format!("1{}", c))
+}
+
+/// 使われている鍵盤のチャンネルからレーンの割り当てを決める.
+/// 8レーン必要な場合（7鍵+スクラッチ）はスクラッチを除く.
+fn assign_lanes(
+    objects: &[BmsObject],
+    warnings: &mut Vec<String>,
+) -> Result<(u8, HashMap<String, i32>), ImportError> {
+    let used = PLAYER_CHANNELS
+        .iter()
+        .filter(|&&channel| {
+            objects.iter().any(|o| {
+                o.channel == channel || long_note_channel(&o.channel).as_deref() == Some(channel)
+            })
+        })
+        .copied()
+        .collect_vec();
+    let used = if used.len() > KeyLane::MAX_KEY_NUM as usize {
+        warnings.push("scratch notes are ignored because 8 lanes are not supported".to_string());
+        used.into_iter()
+            .filter(|c| *c != SCRATCH_CHANNEL)
+            .collect_vec()
+    } else {
+        used
+    };
+    if used.is_empty() {
+        return Err(ImportError::Syntax("no playable notes".to_string()));
+    }
+    if objects
+        .iter()
+        .any(|o| o.channel.starts_with('2') || o.channel.starts_with('6'))
+    {
+        return Err(ImportError::Unsupported(
+            "double play (2P side) charts".to_string(),
+        ));
+    }
+    let lane_num = (used.len() as u8).max(KeyLane::MIN_KEY_NUM);
+    let lanes = used
+        .into_iter()
+        .enumerate()
+        .map(|(i, channel)| (channel.to_string(), i as i32))
+        .collect();
+    Ok((lane_num, lanes))
+}
+
+/// BPM変化点を集める. 03は16進数のBPM, 08は#BPMxxで定義したBPMを指す.
+fn collect_bpms(file: &BmsFile, heads: &BarHeads) -> Result<BpmTimeline, ImportError> {
+    let initial_bpm = file
+        .headers
+        .get("BPM")
+        .map_or(Ok(130.0), |bpm| bpm.parse::<f32>())
+        .map_err(|_| ImportError::Syntax("invalid #BPM".to_string()))?;
+    let mut changes = vec![(0.0, initial_bpm)];
+    for object in file.objects.iter() {
+        let bpm = match object.channel.as_str() {
+            BPM_CHANNEL => {
+                // 03チャンネルだけは16進数として読む
+                let hex = format!("{:02}", radix36(object.id));
+                u32::from_str_radix(&hex, 16).map(|bpm| bpm as f32).ok()
+            }
+            EXTENDED_BPM_CHANNEL => file
+                .headers
+                .get(&format!("BPM{}", radix36(object.id)))
+                .and_then(|bpm| bpm.parse::<f32>().ok()),
+            _ => continue,
+        };
+        let bpm = bpm.ok_or_else(|| {
+            ImportError::Syntax(format!("invalid BPM change at bar {}", object.bar))
+        })?;
+        let beat = heads.beat_at(object.bar, object.position);
+        // 0拍目の変化は最初のBPMを置き換える
+        if beat == 0.0 {
+            changes[0].1 = bpm;
+        } else {
+            changes.push((beat, bpm));
+        }
+    }
+    BpmTimeline::new(changes)
+}
+
+/// 36進数2桁の表記に戻す
+fn radix36(id: u32) -> String {
+    let digit = |d: u32| std::char::from_digit(d, 36).unwrap().to_ascii_uppercase();
+    format!("{}{}", digit(id / 36), digit(id % 36))
+}
+
+/// 通算拍数とノーツの種類, キー音のオブジェクト番号
+type PlacedObject = (f64, NoteType, Option<u32>);
+
+/// 鍵盤のオブジェクトをノーツにする. ロングノーツは#LNTYPE 1（始点と終点の組）と#LNOBJに対応する.
+/// 地雷のオブジェクト番号はダメージ量を表すが, 減点は一定なので使わない.
+fn convert_notes(
+    file: &BmsFile,
+    heads: &BarHeads,
+    lanes: &HashMap<String, i32>,
+    warnings: &mut Vec<String>,
+) -> Vec<PlacedObject> {
+    let ln_obj = file.headers.get("LNOBJ").and_then(|id| parse_object_id(id));
+    let mut notes: Vec<PlacedObject> = vec![];
+    // レーンごとのロングノーツの始点
+    let mut holding = HashMap::<i32, (f64, u32)>::new();
+    let mut next_id = 1;
+    let mut skipped = 0;
+    let objects = file
+        .objects
+        .iter()
+        .map(|o| (heads.beat_at(o.bar, o.position), o))
+        .sorted_by(|a, b| a.0.total_cmp(&b.0));
+    for (beat, object) in objects {
+        if let Some(channel) = mine_channel(&object.channel) {
+            match lanes.get(&channel) {
+                Some(&key) => notes.push((beat, NoteType::Mine { key }, None)),
+                None => skipped += 1,
+            }
+            continue;
+        }
+        let (channel, is_long) = match long_note_channel(&object.channel) {
+            Some(channel) => (channel, true),
+            None => (object.channel.clone(), false),
+        };
+        let Some(&key) = lanes.get(&channel) else {
+            // 鍵盤でないチャンネルのうち, BGM・BPM以外は扱わない
+            if ![BGM_CHANNEL, BPM_CHANNEL, EXTENDED_BPM_CHANNEL].contains(&object.channel.as_str())
+            {
+                skipped += 1;
+            }
+            continue;
+        };
+        if is_long {
+            match holding.remove(&key) {
+                Some((start, sound)) => {
+                    notes.push((
+                        start,
+                        NoteType::Long {
+                            key,
+                            length: (beat - start) as f32,
+                            id: next_id,
+                        },
+                        Some(sound),
+                    ));
+                    next_id += 1;
+                }
+                None => {
+                    holding.insert(key, (beat, object.id));
+                }
+            }
+        } else if Some(object.id) == ln_obj {
+            // 直前の同じレーンのノーツを終点までのロングノーツにする
+            let prev = notes.iter_mut().rev().find(
+                |(_, note_type, _)| matches!(note_type, NoteType::Normal { key: k } if *k == key),
+            );
+            if let Some((start, note_type, _)) = prev {
+                *note_type = NoteType::Long {
+                    key,
+                    length: (beat - *start) as f32,
+                    id: next_id,
+                };
+                next_id += 1;
+            }
+        } else {
+            notes.push((beat, NoteType::Normal { key }, Some(object.id)));
+        }
+    }
+    for (key, (start, sound)) in holding.into_iter().sorted_by_key(|(key, _)| *key) {
+        warnings.push(format!(
+            "long note at beat {} on key {} is not closed",
+            start, key
+        ));
+        notes.push((start, NoteType::Normal { key }, Some(sound)));
+    }
+    if skipped > 0 {
+        warnings.push(format!(
+            "{} objects on unsupported channels (stops, BGA, etc.) are ignored",
+            skipped
+        ));
+    }
+    notes
+}
+
+/// .bmsまたは.bmeファイルの内容を変換する.
+/// 鍵盤のキー音はノーツに持たせて取得したときに鳴らし, BGMチャンネルのキー音はキー音イベントとして常に鳴らす.
+pub fn import_bms(source: &str) -> Result<ImportedChart, ImportError> {
+    let mut warnings = vec![];
+    let file = parse_file(source, &mut warnings)?;
+    let bar_num = file
+        .objects
+        .iter()
+        .map(|o| o.bar)
+        .chain(file.bar_lengths.keys().copied())
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let heads = BarHeads::new(&file.bar_lengths, bar_num);
+    let meters = build_meters(&file, &heads, bar_num, &mut warnings);
+    let bpms = collect_bpms(&file, &heads)?;
+    let (lane_num, lanes) = assign_lanes(&file.objects, &mut warnings)?;
+    let notes = convert_notes(&file, &heads, &lanes, &mut warnings);
+    if file.objects.iter().any(|o| o.channel == STOP_CHANNEL) {
+        warnings.push("stops are ignored".to_string());
+    }
+
+    // 定義されているキー音だけを使われた順に番号を振り直す
+    let mut keysounds = vec![];
+    let mut sound_numbers = HashMap::<u32, u32>::new();
+    let mut number_sound = |id: u32| {
+        let filename = file.headers.get(&format!("WAV{}", radix36(id)))?;
+        Some(*sound_numbers.entry(id).or_insert_with(|| {
+            keysounds.push(filename.clone());
+            keysounds.len() as u32 - 1
+        }))
+    };
+    // 鍵盤のキー音はノーツを取得したときに鳴らす
+    let notes = notes
+        .into_iter()
+        .map(|(beat, note_type, sound)| (beat, note_type, sound.and_then(&mut number_sound)))
+        .collect_vec();
+    // BGMのキー音はノーツの取得に関係なく鳴らす
+    let sound_events = file
+        .objects
+        .iter()
+        .filter(|o| o.channel == BGM_CHANNEL)
+        .filter_map(|o| {
+            let sound = number_sound(o.id)?;
+            Some((heads.beat_at(o.bar, o.position), sound))
+        })
+        .collect_vec();
+
+    let last_beat = notes
+        .iter()
+        .map(|(beat, note_type, _)| match note_type {
+            NoteType::Long { length, .. } => beat + *length as f64,
+            _ => *beat,
+        })
+        .chain(sound_events.iter().map(|(beat, _)| *beat))
+        .fold(0.0, f64::max);
+    let notes = sorted_notes(
+        notes
+            .into_iter()
+            .map(|(beat, note_type, sound)| NoteSpawn {
+                sound,
+                ..note_at(note_type, beat, &meters)
+            })
+            .chain(
+                sound_events
+                    .into_iter()
+                    .map(|(beat, sound)| note_at(NoteType::KeySound { sound }, beat, &meters)),
+            )
+            .chain(bpms.to_notes(&meters))
+            .chain(meters.to_notes()),
+    );
+
+    let difficulty = file
+        .headers
+        .get("DIFFICULTY")
+        .and_then(|d| d.parse::<usize>().ok())
+        .and_then(|d| DIFFICULTY_NAMES.get(d.wrapping_sub(1)))
+        .unwrap_or(&"Normal")
+        .to_string();
+    let config = SongConfig {
+        name: file.headers.get("TITLE").cloned().unwrap_or_default(),
+        // 曲はキー音で構成されるので, 曲のファイルは持たない
+        filename: String::new(),
+        length: bpms.time_at(last_beat) + LENGTH_MARGIN,
+        initial_beat: meters.initial_beat(),
+        initial_bpm: bpms.initial_bpm(),
+        offset: 0.0,
+        lane_num,
+        notes,
+        keysounds,
+    };
+    Ok(ImportedChart {
+        difficulty,
+        level: file
+            .headers
+            .get("PLAYLEVEL")
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(1),
+        config: SongConfigParser::from(config),
+        warnings,
+    })
+}
+
+#[test]
+fn import_bms_test() {
+    let source = "
+*---------------------- HEADER FIELD
+#TITLE Test Song
+#BPM 120
+#PLAYLEVEL 5
+#DIFFICULTY 3
+#WAV01 kick.wav
+#WAV02 snare.wav
+#WAV0Z bgm.wav
+#BPM01 180.5
+#LNOBJ ZZ
+
+*---------------------- MAIN DATA FIELD
+#00001:0Z000000
+#00011:01000000
+#00012:00020000
+#00013:0000ZZ00
+#00013:01000000
+#000D2:00000001
+#00102:0.75
+#00151:010001
+#00203:F0
+#00208:0001
+";
+    let chart = import_bms(source).unwrap();
+    assert_eq!(chart.difficulty, "Hyper");
+    assert_eq!(chart.level, 5);
+    let config = SongConfig::from(chart.config);
+    assert_eq!(config.lane_num, 4);
+    assert_eq!(config.filename, "");
+    assert_eq!(config.keysounds, vec!["kick.wav", "snare.wav", "bgm.wav"]);
+    let notes = config
+        .notes
+        .iter()
+        .map(|note| {
+            (
+                note.note_type.clone(),
+                note.bar,
+                note.beat.as_f64(),
+                note.sound,
+            )
+        })
+        .collect_vec();
+    assert_eq!(
+        notes,
+        vec![
+            (NoteType::Normal { key: 0 }, 0, 0.0, Some(0)),
+            (
+                NoteType::Long {
+                    key: 2,
+                    length: 2.0,
+                    id: 1
+                },
+                0,
+                0.0,
+                Some(0)
+            ),
+            (NoteType::KeySound { sound: 2 }, 0, 0.0, None),
+            (NoteType::Normal { key: 1 }, 0, 1.0, Some(1)),
+            (NoteType::Mine { key: 1 }, 0, 3.0, None),
+            (
+                NoteType::Long {
+                    key: 0,
+                    length: 2.0,
+                    id: 2
+                },
+                1,
+                0.0,
+                Some(0)
+            ),
+            (NoteType::BeatChange { beat: 3 }, 1, 0.0, None),
+            (NoteType::BpmChange { bpm: 240.0 }, 2, 0.0, None),
+            (NoteType::BeatChange { beat: 4 }, 2, 0.0, None),
+            (NoteType::BpmChange { bpm: 180.5 }, 2, 2.0, None),
+        ]
+    );
+}
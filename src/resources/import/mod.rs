@@ -0,0 +1,176 @@
+//! 他のリズムゲームの譜面形式をこのゲームの譜面形式に変換する.
+//! 変換結果は`SongConfigParser`として返すので, そのままyamlに書き出せる.
+
+pub mod bms;
+pub mod osu;
+pub mod stepmania;
+
+use std::fmt;
+
+use itertools::Itertools;
+
+use super::{
+    beat_position::BeatPosition,
+    note::{NoteSpawn, NoteType},
+    song::SongConfigParser,
+};
+
+/// 変換できなかった理由
+#[derive(Debug)]
+pub enum ImportError {
+    /// ファイルの書式が正しくない
+    Syntax(String),
+    /// 書式は正しいが対応していない内容を含む
+    Unsupported(String),
+}
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Syntax(message) => write!(f, "syntax error: {}", message),
+            ImportError::Unsupported(message) => write!(f, "unsupported: {}", message),
+        }
+    }
+}
+impl std::error::Error for ImportError {}
+
+/// 変換された譜面一つ分
+#[derive(Debug)]
+pub struct ImportedChart {
+    /// 元の譜面での難易度名（Hard等）
+    pub difficulty: String,
+    /// 元の譜面でのレベル
+    pub level: u32,
+    pub config: SongConfigParser,
+    /// 変換時に無視した要素など, 変換結果が元の譜面と異なる点
+    pub warnings: Vec<String>,
+}
+
+/// 同じ拍とみなす差
+const BEAT_EPSILON: f64 = 1e-6;
+
+/// 拍数で表されたBPM変化点の列. 拍数は曲頭からの通算.
+pub(super) struct BpmTimeline(Vec<(f64, f32)>);
+impl BpmTimeline {
+    /// 拍数順に並べ, 最初のBPMを求める
+    pub fn new(mut changes: Vec<(f64, f32)>) -> Result<Self, ImportError> {
+        changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        match changes.first() {
+            Some(&(beat, _)) if beat <= 0.0 => Ok(Self(changes)),
+            _ => Err(ImportError::Syntax(
+                "BPM at beat 0 is not specified".to_string(),
+            )),
+        }
+    }
+
+    pub fn initial_bpm(&self) -> f32 {
+        self.0[0].1
+    }
+
+    /// 通算拍数から曲頭（0拍目）からの時刻（秒）を求める
+    pub fn time_at(&self, beat: f64) -> f64 {
+        let mut time = 0.0;
+        for (i, &(start, bpm)) in self.0.iter().enumerate() {
+            let end = self.0.get(i + 1).map_or(f64::INFINITY, |next| next.0);
+            if beat <= start {
+                break;
+            }
+            time += (beat.min(end) - start) * 60.0 / bpm as f64;
+        }
+        time
+    }
+
+    /// 最初のBPM以外をBPM変更ノーツにする
+    pub fn to_notes(&self, meters: &MeterTimeline) -> Vec<NoteSpawn> {
+        self.0
+            .iter()
+            .skip(1)
+            .map(|&(beat, bpm)| note_at(NoteType::BpmChange { bpm }, beat, meters))
+            .collect()
+    }
+}
+
+/// 拍子の変化点の列. 小節の頭の通算拍数, 小節番号, 一小節の拍数を持つ.
+pub(super) struct MeterTimeline(Vec<(f64, u32, u32)>);
+impl MeterTimeline {
+    pub fn new(initial_beat: u32) -> Self {
+        Self(vec![(0.0, 0, initial_beat)])
+    }
+
+    pub fn initial_beat(&self) -> u32 {
+        self.0[0].2
+    }
+
+    /// 通算拍数の位置から拍子を変える. 拍子変更は小節の頭にしか置けないので,
+    /// 小節の途中であれば次の小節から変え, `false`を返す.
+    pub fn change(&mut self, total_beat: f64, beat_par_bar: u32) -> bool {
+        let (bar, beat) = self.position(total_beat);
+        let at_head = beat.abs() < BEAT_EPSILON;
+        let (bar, head) = if at_head {
+            (bar, total_beat)
+        } else {
+            let &(prev_head, prev_bar, prev_beat_par_bar) = self.last_before(total_beat);
+            let bar = bar + 1;
+            (
+                bar,
+                prev_head + ((bar - prev_bar) * prev_beat_par_bar) as f64,
+            )
+        };
+        self.0.retain(|&(_, b, _)| b < bar);
+        if self.0.last().map(|last| last.2) != Some(beat_par_bar) {
+            self.0.push((head, bar, beat_par_bar));
+        }
+        at_head
+    }
+
+    fn last_before(&self, total_beat: f64) -> &(f64, u32, u32) {
+        self.0
+            .iter()
+            .rev()
+            .find(|(head, _, _)| *head <= total_beat + BEAT_EPSILON)
+            .unwrap_or(&self.0[0])
+    }
+
+    /// 通算拍数から小節番号と小節内の拍位置を求める
+    pub fn position(&self, total_beat: f64) -> (u32, f64) {
+        let &(head, bar, beat_par_bar) = self.last_before(total_beat);
+        let beat_par_bar = beat_par_bar as f64;
+        let bars = ((total_beat - head) / beat_par_bar + BEAT_EPSILON).floor();
+        let beat = total_beat - head - bars * beat_par_bar;
+        (
+            bar + bars as u32,
+            if beat.abs() < BEAT_EPSILON { 0.0 } else { beat },
+        )
+    }
+
+    /// 最初の拍子以外を拍子変更ノーツにする
+    pub fn to_notes(&self) -> Vec<NoteSpawn> {
+        self.0
+            .iter()
+            .skip(1)
+            .map(|&(_, bar, beat)| NoteSpawn {
+                note_type: NoteType::BeatChange { beat },
+                bar,
+                beat: BeatPosition::ZERO,
+                sound: None,
+            })
+            .collect()
+    }
+}
+
+/// 通算拍数の位置にノーツを置く. 拍位置は近い分数に直す.
+pub(super) fn note_at(note_type: NoteType, total_beat: f64, meters: &MeterTimeline) -> NoteSpawn {
+    let (bar, beat) = meters.position(total_beat);
+    NoteSpawn {
+        note_type,
+        bar,
+        beat: BeatPosition::from_f64(beat).unwrap_or_default(),
+        sound: None,
+    }
+}
+
+/// ノーツを小節と拍の順に並べる
+pub(super) fn sorted_notes(notes: impl Iterator<Item = NoteSpawn>) -> Vec<NoteSpawn> {
+    notes
+        .sorted_by_key(|note| (note.bar, note.beat))
+        .collect_vec()
+}
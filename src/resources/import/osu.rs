@@ -0,0 +1,454 @@
+//! osu!maniaの譜面ファイル（.osu）との相互変換.
+//! osu!では位置をミリ秒で表し, BPMと拍子は赤線（uninheritedなタイミングポイント）で指定する.
+//! 読み込み時はタイミングポイントから通算拍数を求めて小節と拍に直す.
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use super::{note_at, sorted_notes, BpmTimeline, ImportError, ImportedChart, MeterTimeline};
+use crate::{
+    components::note::KeyLane,
+    resources::{
+        note::NoteType,
+        song::{SongConfig, SongConfigParser},
+        timing::place_notes,
+    },
+};
+
+/// osu!上のx座標の幅. 鍵盤はこれを等分した位置で表される.
+const PLAYFIELD_WIDTH: f64 = 512.0;
+/// ホールドノーツを表すビット
+const HOLD_NOTE: u32 = 128;
+/// osu!maniaのモード番号
+const MANIA_MODE: &str = "3";
+/// 最後のノーツから曲の終わりまでの余白（秒）
+const LENGTH_MARGIN: f64 = 2.0;
+/// 拍位置を細かい音符に寄せる単位（一拍を何分割するか）
+const SNAP_DIVISION: f64 = 48.0;
+/// 拍位置を寄せてよい時間のずれ（ミリ秒）
+const SNAP_TOLERANCE: f64 = 2.0;
+
+/// 赤線. 時刻（ミリ秒）, 一拍の長さ（ミリ秒）, 一小節の拍数.
+#[derive(Debug, Clone, Copy)]
+struct TimingPoint {
+    time: f64,
+    beat_length: f64,
+    meter: u32,
+}
+
+/// 譜面ファイルの内容. `[セクション名]`ごとに行を持つ.
+struct OsuFile<'a> {
+    sections: HashMap<&'a str, Vec<&'a str>>,
+}
+impl<'a> OsuFile<'a> {
+    fn parse(source: &'a str) -> Self {
+        let mut sections = HashMap::<&str, Vec<&str>>::new();
+        let mut current = "";
+        for line in source.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                current = &line[1..line.len() - 1];
+                sections.entry(current).or_default();
+            } else {
+                sections.entry(current).or_default().push(line);
+            }
+        }
+        Self { sections }
+    }
+
+    fn lines(&self, section: &str) -> &[&'a str] {
+        self.sections
+            .get(section)
+            .map_or(&[], |lines| lines.as_slice())
+    }
+
+    /// `key:value`形式のセクションから値を探す
+    fn value(&self, section: &str, key: &str) -> Option<&'a str> {
+        self.lines(section).iter().find_map(|line| {
+            let (k, v) = line.split_once(':')?;
+            (k.trim() == key).then_some(v.trim())
+        })
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, ImportError> {
+    s.trim()
+        .parse::<T>()
+        .map_err(|_| ImportError::Syntax(format!("invalid number: {}", s)))
+}
+
+/// 赤線を時刻順に読む. 緑線（速度変化）は無視する.
+fn parse_timing_points(
+    file: &OsuFile,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<TimingPoint>, ImportError> {
+    let mut points: Vec<TimingPoint> = vec![];
+    let mut ignored = 0;
+    for line in file.lines("TimingPoints") {
+        let fields = line.split(',').collect_vec();
+        if fields.len() < 2 {
+            return Err(ImportError::Syntax(format!(
+                "invalid timing point: {}",
+                line
+            )));
+        }
+        let beat_length = parse_number::<f64>(fields[1])?;
+        let uninherited = fields.get(6).is_none_or(|f| f.trim() == "1");
+        if !uninherited || beat_length <= 0.0 {
+            ignored += 1;
+            continue;
+        }
+        let point = TimingPoint {
+            time: parse_number(fields[0])?,
+            beat_length,
+            meter: fields.get(2).map_or(Ok(4), |f| parse_number(f))?,
+        };
+        // 同じ時刻の赤線は後のものが優先される
+        match points.last_mut() {
+            Some(last) if last.time == point.time => *last = point,
+            _ => points.push(point),
+        }
+    }
+    if ignored > 0 {
+        warnings.push(format!(
+            "{} inherited timing points (scroll speed changes) are ignored",
+            ignored
+        ));
+    }
+    points.sort_by(|a, b| a.time.total_cmp(&b.time));
+    if points.is_empty() {
+        return Err(ImportError::Syntax(
+            "no uninherited timing point".to_string(),
+        ));
+    }
+    Ok(points)
+}
+
+/// 赤線ごとの区間. 区間の始まりの通算拍数を持つ.
+struct Sections(Vec<(TimingPoint, f64)>);
+impl Sections {
+    /// 最初の赤線より前のノーツも置けるように, 0拍目を`first_note`以前の小節の頭にずらす.
+    /// 0拍目の時刻（ミリ秒）も返す.
+    fn new(points: &[TimingPoint], first_note: f64) -> (Self, f64) {
+        let first = points[0];
+        let bar_length = first.beat_length * first.meter as f64;
+        let lead_bars = ((first.time - first_note) / bar_length).ceil().max(0.0);
+        let origin = first.time - lead_bars * bar_length;
+
+        let mut sections = vec![];
+        let mut total_beat = lead_bars * first.meter as f64;
+        let mut prev = first;
+        for &point in points {
+            total_beat += (point.time - prev.time) / prev.beat_length;
+            let total_beat = snap_beat(total_beat, prev.beat_length);
+            sections.push((point, total_beat));
+            prev = point;
+        }
+        (Self(sections), origin)
+    }
+
+    /// ミリ秒から通算拍数を求める
+    fn beat_at(&self, time: f64) -> f64 {
+        let (point, start) = self
+            .0
+            .iter()
+            .rev()
+            .find(|(point, _)| point.time <= time)
+            .unwrap_or(&self.0[0]);
+        snap_beat(
+            start + (time - point.time) / point.beat_length,
+            point.beat_length,
+        )
+    }
+}
+
+/// ミリ秒単位の誤差を持つ拍位置を, 近い細かい音符の位置に寄せる
+fn snap_beat(total_beat: f64, beat_length: f64) -> f64 {
+    let snapped = (total_beat * SNAP_DIVISION).round() / SNAP_DIVISION;
+    if (snapped - total_beat).abs() * beat_length <= SNAP_TOLERANCE {
+        snapped
+    } else {
+        (total_beat * 1000.0).round() / 1000.0
+    }
+}
+
+/// 鍵盤の位置, 開始時刻, ホールドの終了時刻
+type HitObject = (i32, f64, Option<f64>);
+
+fn parse_hit_objects(file: &OsuFile, lane_num: u8) -> Result<Vec<HitObject>, ImportError> {
+    file.lines("HitObjects")
+        .iter()
+        .map(|line| {
+            let fields = line.split(',').collect_vec();
+            if fields.len() < 5 {
+                return Err(ImportError::Syntax(format!("invalid hit object: {}", line)));
+            }
+            let x = parse_number::<f64>(fields[0])?;
+            let key = ((x * lane_num as f64 / PLAYFIELD_WIDTH).floor() as i32)
+                .clamp(0, lane_num as i32 - 1);
+            let time = parse_number(fields[2])?;
+            let end_time = if parse_number::<u32>(fields[3])? & HOLD_NOTE != 0 {
+                let params = fields.get(5).unwrap_or(&"");
+                Some(parse_number(params.split(':').next().unwrap_or(""))?)
+            } else {
+                None
+            };
+            Ok((key, time, end_time))
+        })
+        .collect()
+}
+
+/// .osuファイルの内容を変換する. osu!maniaの譜面のみ対応する.
+pub fn import_osu(source: &str) -> Result<ImportedChart, ImportError> {
+    let file = OsuFile::parse(source);
+    if file.value("General", "Mode") != Some(MANIA_MODE) {
+        return Err(ImportError::Unsupported(
+            "not an osu!mania beatmap".to_string(),
+        ));
+    }
+    let lane_num = file
+        .value("Difficulty", "CircleSize")
+        .ok_or_else(|| ImportError::Syntax("CircleSize is not specified".to_string()))
+        .and_then(parse_number::<f64>)? as u8;
+    if !(KeyLane::MIN_KEY_NUM..=KeyLane::MAX_KEY_NUM).contains(&lane_num) {
+        return Err(ImportError::Unsupported(format!(
+            "{}K (only {}K..={}K are supported)",
+            lane_num,
+            KeyLane::MIN_KEY_NUM,
+            KeyLane::MAX_KEY_NUM
+        )));
+    }
+
+    let mut warnings = vec![];
+    let points = parse_timing_points(&file, &mut warnings)?;
+    let objects = parse_hit_objects(&file, lane_num)?;
+    let first_note = objects.iter().map(|o| o.1).fold(f64::INFINITY, f64::min);
+    let (sections, origin) = Sections::new(&points, first_note);
+
+    let bpms = BpmTimeline::new(
+        sections
+            .0
+            .iter()
+            .map(|(point, beat)| (*beat, (60000.0 / point.beat_length) as f32))
+            .dedup_by(|a, b| a.1 == b.1)
+            .enumerate()
+            // 0拍目から最初の赤線のBPMとする
+            .map(|(i, (beat, bpm))| (if i == 0 { 0.0 } else { beat }, bpm))
+            .collect_vec(),
+    )?;
+    let mut meters = MeterTimeline::new(points[0].meter);
+    for (point, beat) in sections.0.iter().skip(1) {
+        if !meters.change(*beat, point.meter) {
+            warnings.push(format!(
+                "meter change at {} ms is moved to the next bar",
+                point.time
+            ));
+        }
+    }
+
+    let mut next_id = 1;
+    let mut last_time = first_note.max(0.0);
+    let notes = objects.into_iter().map(|(key, time, end_time)| {
+        let beat = sections.beat_at(time);
+        last_time = last_time.max(end_time.unwrap_or(time));
+        let note_type = match end_time {
+            Some(end_time) => {
+                let id = next_id;
+                next_id += 1;
+                NoteType::Long {
+                    key,
+                    length: (sections.beat_at(end_time) - beat) as f32,
+                    id,
+                }
+            }
+            None => NoteType::Normal { key },
+        };
+        note_at(note_type, beat, &meters)
+    });
+    let notes = sorted_notes(
+        notes
+            .collect_vec()
+            .into_iter()
+            .chain(bpms.to_notes(&meters))
+            .chain(meters.to_notes()),
+    );
+
+    let config = SongConfig {
+        name: file.value("Metadata", "Title").unwrap_or("").to_string(),
+        filename: file
+            .value("General", "AudioFilename")
+            .unwrap_or("")
+            .to_string(),
+        length: last_time / 1000.0 + LENGTH_MARGIN,
+        initial_beat: meters.initial_beat(),
+        initial_bpm: bpms.initial_bpm(),
+        offset: origin,
+        lane_num,
+        notes,
+        keysounds: vec![],
+    };
+    Ok(ImportedChart {
+        difficulty: file.value("Metadata", "Version").unwrap_or("").to_string(),
+        // osu!には譜面のレベルがないのでOD（判定の厳しさ）で代用する
+        level: file
+            .value("Difficulty", "OverallDifficulty")
+            .and_then(|od| od.parse::<f64>().ok())
+            .map_or(1, |od| od.round().max(1.0) as u32),
+        config: SongConfigParser::from(config),
+        warnings,
+    })
+}
+
+/// 譜面を.osuファイルの内容に変換する. アドリブノーツは見えないノーツなので出力しない.
+/// osu!maniaには地雷がないので地雷も出力しない.
+pub fn export_osu(config: &SongConfig, version: &str) -> String {
+    // 0小節目の頭を0秒として置き, 譜面のオフセット（ミリ秒）は書き出すときに足す
+    let placed = place_notes(&config.notes, config.initial_bpm, config.initial_beat, 0.0);
+    let time_at = |time: f64| (config.offset + time * 1000.0).round();
+
+    // BPMか拍子が変わる位置ごとに赤線を置く
+    let timing_points = std::iter::once((0.0, config.initial_bpm, config.initial_beat))
+        .chain(placed.iter().filter_map(|p| match p.note.note_type {
+            NoteType::BpmChange { .. } | NoteType::BeatChange { .. } => {
+                Some((p.time, p.bpm, p.beat_par_bar))
+            }
+            _ => None,
+        }))
+        .coalesce(|a, b| if a.0 == b.0 { Ok(b) } else { Err((a, b)) })
+        .map(|(time, bpm, meter)| {
+            format!(
+                "{},{},{},1,0,100,1,0",
+                time_at(time),
+                60000.0 / bpm as f64,
+                meter
+            )
+        })
+        .join("\n");
+
+    let x = |key: i32| ((key as f64 + 0.5) * PLAYFIELD_WIDTH / config.lane_num as f64).floor();
+    let hit_objects = placed
+        .iter()
+        .filter_map(|p| match p.note.note_type {
+            NoteType::Normal { key } => {
+                Some(format!("{},192,{},1,0,0:0:0:0:", x(key), time_at(p.time)))
+            }
+            // osu!maniaにはロールがないのでホールドとして出力する
+            NoteType::Long { key, .. } | NoteType::Roll { key, .. } => Some(format!(
+                "{},192,{},{},0,{}:0:0:0:0:",
+                x(key),
+                time_at(p.time),
+                HOLD_NOTE,
+                time_at(p.end_time)
+            )),
+            _ => None,
+        })
+        .join("\n");
+
+    format!(
+        "osu file format v14
+
+[General]
+AudioFilename: {filename}
+AudioLeadIn: 0
+PreviewTime: -1
+Mode: {mode}
+
+[Metadata]
+Title:{name}
+TitleUnicode:{name}
+Artist:Unknown
+Creator:rhythm_2
+Version:{version}
+
+[Difficulty]
+HPDrainRate:8
+CircleSize:{lane_num}
+OverallDifficulty:8
+ApproachRate:5
+SliderMultiplier:1.4
+SliderTickRate:1
+
+[TimingPoints]
+{timing_points}
+
+[HitObjects]
+{hit_objects}
+",
+        filename = config.filename,
+        mode = MANIA_MODE,
+        name = config.name,
+        version = version,
+        lane_num = config.lane_num,
+    )
+}
+
+#[test]
+fn osu_round_trip_test() {
+    let source = "osu file format v14
+
+[General]
+AudioFilename: test.mp3
+Mode: 3
+
+[Metadata]
+Title:Test Song
+Version:Hard
+
+[Difficulty]
+CircleSize:4
+OverallDifficulty:7.5
+
+[TimingPoints]
+1000,500,4,1,0,100,1,0
+2000,-50,4,1,0,100,0,0
+3000,250,4,1,0,100,1,0
+
+[HitObjects]
+64,192,500,1,0,0:0:0:0:
+192,192,1000,1,0,0:0:0:0:
+320,192,1250,128,0,2000:0:0:0:0:
+448,192,3125,1,0,0:0:0:0:
+";
+    let chart = import_osu(source).unwrap();
+    assert_eq!(chart.difficulty, "Hard");
+    assert_eq!(chart.level, 8);
+    let config = SongConfig::from(chart.config.clone());
+    assert_eq!(config.lane_num, 4);
+    assert_eq!(config.initial_bpm, 120.0);
+    // 500msのノーツが置けるように1小節前にずらされる
+    assert_eq!(config.offset, -1000.0);
+    let notes = config
+        .notes
+        .iter()
+        .map(|note| (note.note_type.clone(), note.bar, note.beat.as_f64()))
+        .collect_vec();
+    assert_eq!(
+        notes,
+        vec![
+            (NoteType::Normal { key: 0 }, 0, 3.0),
+            (NoteType::Normal { key: 1 }, 1, 0.0),
+            (
+                NoteType::Long {
+                    key: 2,
+                    length: 1.5,
+                    id: 1
+                },
+                1,
+                0.5
+            ),
+            (NoteType::BpmChange { bpm: 240.0 }, 2, 0.0),
+            (NoteType::Normal { key: 3 }, 2, 0.5),
+        ]
+    );
+
+    // 書き出したものを読み直すと同じ譜面になる
+    let exported = export_osu(&config, "Hard");
+    let reimported = import_osu(&exported).unwrap();
+    assert_eq!(
+        serde_yaml::to_string(&reimported.config).unwrap(),
+        serde_yaml::to_string(&chart.config).unwrap()
+    );
+}
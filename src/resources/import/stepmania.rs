@@ -0,0 +1,372 @@
+//! StepManiaの譜面ファイル（.sm, .ssc）を読み込む.
+//! 一つのファイルに複数の譜面（難易度）が含まれるため, 譜面ごとに変換する.
+//! StepManiaの譜面は4拍子固定で, 小節を`,`で区切り, 小節内を等分した行で位置を表す.
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use super::{note_at, sorted_notes, BpmTimeline, ImportError, ImportedChart, MeterTimeline};
+use crate::{
+    components::note::KeyLane,
+    resources::{
+        note::NoteType,
+        song::{SongConfig, SongConfigParser},
+    },
+};
+
+/// StepManiaの一小節の拍数
+const BEAT_PAR_BAR: u32 = 4;
+/// 最後のノーツから曲の終わりまでの余白（秒）
+const LENGTH_MARGIN: f64 = 2.0;
+
+/// 通算拍数とその行の各レーンの文字
+type Row = (f64, Vec<char>);
+
+/// `#TAG:value;`の列. 同じタグが複数回現れることがあるので順番を保って持つ.
+fn parse_tags(source: &str) -> Vec<(String, String)> {
+    // `//`以降はコメント
+    let source = source
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or(""))
+        .join("\n");
+    source
+        .split(';')
+        .filter_map(|tag| {
+            let tag = tag.trim();
+            let tag = &tag[tag.find('#')? + 1..];
+            let (name, value) = tag.split_once(':').unwrap_or((tag, ""));
+            Some((name.trim().to_uppercase(), value.trim().to_string()))
+        })
+        .collect_vec()
+}
+
+/// `拍=値,拍=値,...`の形式を読む
+fn parse_beat_values(value: &str) -> Result<Vec<(f64, f64)>, ImportError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (beat, value) = pair
+                .split_once('=')
+                .ok_or_else(|| ImportError::Syntax(format!("invalid pair: {}", pair)))?;
+            let parse = |s: &str| {
+                s.trim()
+                    .parse::<f64>()
+                    .map_err(|_| ImportError::Syntax(format!("invalid number: {}", s)))
+            };
+            Ok((parse(beat)?, parse(value)?))
+        })
+        .collect()
+}
+
+/// 譜面ごとに異なりうる情報. sscでは譜面ごとにBPM等を上書きできる.
+#[derive(Default, Clone)]
+struct ChartTags {
+    difficulty: String,
+    meter: String,
+    notes: String,
+    bpms: Option<String>,
+    offset: Option<String>,
+    stops: Option<String>,
+}
+
+/// ファイル内の曲情報と譜面の一覧
+struct SmFile {
+    title: String,
+    music: String,
+    bpms: String,
+    offset: String,
+    stops: String,
+    charts: Vec<ChartTags>,
+}
+
+fn parse_file(source: &str) -> SmFile {
+    let mut file = SmFile {
+        title: String::new(),
+        music: String::new(),
+        bpms: String::new(),
+        offset: "0".to_string(),
+        stops: String::new(),
+        charts: vec![],
+    };
+    // sscで#NOTEDATAから#NOTESまでの間に書かれる譜面
+    let mut current: Option<ChartTags> = None;
+    for (name, value) in parse_tags(source) {
+        match (name.as_str(), current.as_mut()) {
+            ("NOTEDATA", _) => current = Some(ChartTags::default()),
+            ("DIFFICULTY", Some(chart)) => chart.difficulty = value,
+            ("METER", Some(chart)) => chart.meter = value,
+            ("BPMS", Some(chart)) => chart.bpms = Some(value),
+            ("OFFSET", Some(chart)) => chart.offset = Some(value),
+            ("STOPS" | "DELAYS" | "WARPS", Some(chart)) if !value.is_empty() => {
+                chart.stops = Some(value)
+            }
+            ("NOTES", Some(_)) => {
+                let mut chart = current.take().unwrap();
+                chart.notes = value;
+                file.charts.push(chart);
+            }
+            // smでは#NOTES:種類:説明:難易度:レベル:グルーブレーダー:ノーツ;の形式
+            ("NOTES", None) => {
+                let fields = value.splitn(6, ':').map(str::trim).collect_vec();
+                if let [_, _, difficulty, meter, _, notes] = fields[..] {
+                    file.charts.push(ChartTags {
+                        difficulty: difficulty.to_string(),
+                        meter: meter.to_string(),
+                        notes: notes.to_string(),
+                        ..Default::default()
+                    });
+                }
+            }
+            ("TITLE", _) => file.title = value,
+            ("MUSIC", _) => file.music = value,
+            ("BPMS", None) => file.bpms = value,
+            ("OFFSET", None) => file.offset = value,
+            ("STOPS" | "DELAYS" | "WARPS", None) if !value.is_empty() => file.stops = value,
+            _ => {}
+        }
+    }
+    file
+}
+
+/// 小節ごとの行の列から, 通算拍数とその行の文字列の列にする
+fn parse_rows(notes: &str) -> Result<(u8, Vec<Row>), ImportError> {
+    let mut lane_num = None;
+    let mut rows = vec![];
+    for (bar, measure) in notes.split(',').enumerate() {
+        let measure_rows = measure
+            .split_whitespace()
+            .map(|row| row.chars().collect_vec())
+            .collect_vec();
+        let row_num = measure_rows.len();
+        for (i, row) in measure_rows.into_iter().enumerate() {
+            let width = *lane_num.get_or_insert(row.len());
+            if row.len() != width {
+                return Err(ImportError::Syntax(format!(
+                    "bar {} has a row of width {} (expected {})",
+                    bar,
+                    row.len(),
+                    width
+                )));
+            }
+            let beat = (bar as u32 * BEAT_PAR_BAR) as f64
+                + (i as u32 * BEAT_PAR_BAR) as f64 / row_num as f64;
+            rows.push((beat, row));
+        }
+    }
+    let lane_num = lane_num.ok_or_else(|| ImportError::Syntax("chart has no rows".to_string()))?;
+    if !(KeyLane::MIN_KEY_NUM as usize..=KeyLane::MAX_KEY_NUM as usize).contains(&lane_num) {
+        return Err(ImportError::Unsupported(format!(
+            "{} columns (only {}..={} are supported)",
+            lane_num,
+            KeyLane::MIN_KEY_NUM,
+            KeyLane::MAX_KEY_NUM
+        )));
+    }
+    Ok((lane_num as u8, rows))
+}
+
+/// 行の列からノーツを作る. ロングノーツの終点の通算拍数も合わせて返す.
+fn convert_rows(rows: Vec<Row>, warnings: &mut Vec<String>) -> (Vec<(f64, NoteType)>, f64) {
+    let mut notes = vec![];
+    // レーンごとの押し始めの拍と, ロールかどうか
+    let mut holding = HashMap::<i32, (f64, bool)>::new();
+    let mut next_id = 1;
+    let mut last_beat = 0.0f64;
+    let mut skipped = 0;
+    for (beat, row) in rows {
+        for (key, c) in row.into_iter().enumerate() {
+            let key = key as i32;
+            match c {
+                '0' => continue,
+                '1' | 'L' => notes.push((beat, NoteType::Normal { key })),
+                'M' => notes.push((beat, NoteType::Mine { key })),
+                // ホールドとロールの始点
+                '2' | '4' => {
+                    holding.insert(key, (beat, c == '4'));
+                }
+                '3' => match holding.remove(&key) {
+                    Some((start, is_roll)) => {
+                        let length = (beat - start) as f32;
+                        let note_type = if is_roll {
+                            NoteType::Roll {
+                                key,
+                                length,
+                                id: next_id,
+                            }
+                        } else {
+                            NoteType::Long {
+                                key,
+                                length,
+                                id: next_id,
+                            }
+                        };
+                        notes.push((start, note_type));
+                        next_id += 1;
+                    }
+                    None => warnings.push(format!("hold end without start at beat {}", beat)),
+                },
+                // フェイク, キー音等は扱わない
+                _ => skipped += 1,
+            }
+            last_beat = last_beat.max(beat);
+        }
+    }
+    // 閉じられていないホールドは通常ノーツとする
+    for (key, (start, _)) in holding.into_iter().sorted_by_key(|(key, _)| *key) {
+        warnings.push(format!(
+            "hold at beat {} on key {} is not closed",
+            start, key
+        ));
+        notes.push((start, NoteType::Normal { key }));
+    }
+    if skipped > 0 {
+        warnings.push(format!(
+            "{} unsupported notes (fakes, keysounds, etc.) are ignored",
+            skipped
+        ));
+    }
+    (notes, last_beat)
+}
+
+fn convert_chart(file: &SmFile, chart: &ChartTags) -> Result<ImportedChart, ImportError> {
+    let mut warnings = vec![];
+    let bpms = parse_beat_values(chart.bpms.as_ref().unwrap_or(&file.bpms))?;
+    let timeline = BpmTimeline::new(
+        bpms.into_iter()
+            .map(|(beat, bpm)| (beat, bpm as f32))
+            .collect_vec(),
+    )?;
+    let sm_offset = chart
+        .offset
+        .as_ref()
+        .unwrap_or(&file.offset)
+        .parse::<f64>()
+        .map_err(|_| ImportError::Syntax("invalid #OFFSET".to_string()))?;
+    if chart.stops.is_some() || !file.stops.is_empty() {
+        warnings.push("stops, delays and warps are ignored".to_string());
+    }
+
+    let (lane_num, rows) = parse_rows(&chart.notes)?;
+    let (notes, last_beat) = convert_rows(rows, &mut warnings);
+    // StepManiaの#OFFSETは0拍目の時刻を負にしたもの
+    let offset = -sm_offset * 1000.0;
+    let length = offset / 1000.0 + timeline.time_at(last_beat) + LENGTH_MARGIN;
+
+    let meters = MeterTimeline::new(BEAT_PAR_BAR);
+    let notes = sorted_notes(
+        notes
+            .into_iter()
+            .map(|(beat, note_type)| note_at(note_type, beat, &meters))
+            .chain(timeline.to_notes(&meters)),
+    );
+    let config = SongConfig {
+        name: file.title.clone(),
+        filename: file.music.clone(),
+        length,
+        initial_beat: BEAT_PAR_BAR,
+        initial_bpm: timeline.initial_bpm(),
+        offset,
+        lane_num,
+        notes,
+        keysounds: vec![],
+    };
+    Ok(ImportedChart {
+        difficulty: chart.difficulty.clone(),
+        level: chart.meter.parse().unwrap_or(1),
+        config: SongConfigParser::from(config),
+        warnings,
+    })
+}
+
+/// .smまたは.sscファイルの内容から, 含まれる全ての譜面を変換する
+pub fn import_stepmania(source: &str) -> Result<Vec<ImportedChart>, ImportError> {
+    let file = parse_file(source);
+    if file.charts.is_empty() {
+        return Err(ImportError::Syntax("no #NOTES found".to_string()));
+    }
+    file.charts
+        .iter()
+        .map(|chart| convert_chart(&file, chart))
+        .collect()
+}
+
+#[test]
+fn import_stepmania_test() {
+    let source = "
+#TITLE:Test Song;
+#MUSIC:test.ogg;
+#OFFSET:-0.100;
+#BPMS:0.000=120.000,8.000=240.000;
+#STOPS:;
+#NOTES:
+     dance-single:
+     :
+     Hard:
+     7:
+     0,0,0,0,0:
+// measure 0
+1000
+0100
+2040
+0030
+,
+3000
+0000
+M000
+0F01
+;
+";
+    let charts = import_stepmania(source).unwrap();
+    assert_eq!(charts.len(), 1);
+    let chart = &charts[0];
+    assert_eq!(chart.difficulty, "Hard");
+    assert_eq!(chart.level, 7);
+    assert_eq!(chart.config.lane_num, 4);
+    assert_eq!(chart.config.initial_bpm, 120.0);
+    assert_eq!(chart.config.offset, 100.0);
+
+    // yamlに書き出して読み直しても同じになる
+    let yaml = serde_yaml::to_string(&chart.config).unwrap();
+    let parsed = serde_yaml::from_str::<SongConfigParser>(&yaml).unwrap();
+    assert_eq!(yaml, serde_yaml::to_string(&parsed).unwrap());
+
+    let config = SongConfig::from(parsed);
+    let notes = config
+        .notes
+        .iter()
+        .map(|note| (note.note_type.clone(), note.bar, note.beat.as_f64()))
+        .collect_vec();
+    assert_eq!(
+        notes,
+        vec![
+            (NoteType::Normal { key: 0 }, 0, 0.0),
+            (NoteType::Normal { key: 1 }, 0, 1.0),
+            (
+                NoteType::Roll {
+                    key: 2,
+                    length: 1.0,
+                    id: 1
+                },
+                0,
+                2.0
+            ),
+            (
+                NoteType::Long {
+                    key: 0,
+                    length: 2.0,
+                    id: 2
+                },
+                0,
+                2.0
+            ),
+            (NoteType::Mine { key: 0 }, 1, 2.0),
+            (NoteType::Normal { key: 3 }, 1, 3.0),
+            (NoteType::BpmChange { bpm: 240.0 }, 2, 0.0),
+        ]
+    );
+    // フェイクノーツが無視された旨の警告
+    assert_eq!(chart.warnings.len(), 1);
+}
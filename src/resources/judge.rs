@@ -0,0 +1,232 @@
+//! ノーツ取得の判定幅を定義する.
+//! 判定幅は名前付きのプリセットから選び, 設定ファイル（`assets/config.judge.yaml`）で指定する.
+//! ノーツの種類ごとに判定幅を広げたり狭めたりすることもできる.
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    note::NoteType,
+    score::{CatchEval, TimingEval},
+};
+
+/// 判定幅の設定ファイルのパス（assetsからの相対パス）.
+/// 譜面と区別するために拡張子を`judge.yaml`としている.
+pub const JUDGE_CONFIG_PATH: &str = "config.judge.yaml";
+
+/// 判定幅. 時刻誤差（秒）がそれぞれの値以下であればその評価になり, `ok`を超えるとミスになる.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct JudgeWindow {
+    pub perfect: f64,
+    pub near_perfect: f64,
+    pub ok: f64,
+}
+impl JudgeWindow {
+    /// 判定幅を一律に`scale`倍する
+    pub fn scaled(&self, scale: f64) -> Self {
+        Self {
+            perfect: self.perfect * scale,
+            near_perfect: self.near_perfect * scale,
+            ok: self.ok * scale,
+        }
+    }
+
+    /// 判定できる時刻の範囲に入っているか
+    pub fn contains(&self, target_time: f64, real_time: f64) -> bool {
+        (target_time - self.ok..=target_time + self.ok).contains(&real_time)
+    }
+
+    /// 目標時刻と実際の時刻から評価する
+    pub fn eval(&self, target_time: f64, real_time: f64) -> CatchEval {
+        let diff = real_time - target_time;
+        let timing = if diff < 0.0 {
+            TimingEval::Fast
+        } else {
+            TimingEval::Slow
+        };
+        match diff.abs() {
+            d if d > self.ok => CatchEval::Miss,
+            d if d > self.near_perfect => CatchEval::Ok(timing),
+            d if d > self.perfect => CatchEval::NearPerfect(timing),
+            _ => CatchEval::Perfect,
+        }
+    }
+
+    /// 判定幅が正で, 評価の順に広がっているか
+    fn is_valid(&self) -> bool {
+        0.0 < self.perfect && self.perfect <= self.near_perfect && self.near_perfect <= self.ok
+    }
+}
+
+/// 判定幅のプリセット
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JudgePreset {
+    Lenient,
+    #[default]
+    Standard,
+    Strict,
+}
+impl JudgePreset {
+    pub fn window(&self) -> JudgeWindow {
+        match self {
+            JudgePreset::Lenient => JudgeWindow {
+                perfect: 0.025,
+                near_perfect: 0.05,
+                ok: 0.15,
+            },
+            JudgePreset::Standard => JudgeWindow {
+                perfect: 0.1 / 6.0,
+                near_perfect: 0.1 / 3.0,
+                ok: 0.1,
+            },
+            JudgePreset::Strict => JudgeWindow {
+                perfect: 0.0125,
+                near_perfect: 0.025,
+                ok: 0.075,
+            },
+        }
+    }
+}
+impl std::fmt::Display for JudgePreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JudgePreset::Lenient => write!(f, "lenient"),
+            JudgePreset::Standard => write!(f, "standard"),
+            JudgePreset::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+/// 判定幅を変えられるノーツの種類. 地雷は通常ノーツと同じ判定幅を使う.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum JudgeNoteKind {
+    Normal,
+    Long,
+    Roll,
+    AdLib,
+}
+impl JudgeNoteKind {
+    fn from_note_type(note_type: &NoteType) -> Option<Self> {
+        match note_type {
+            NoteType::Normal { key: _ } | NoteType::Mine { key: _ } => Some(Self::Normal),
+            NoteType::Long {
+                key: _,
+                length: _,
+                id: _,
+            } => Some(Self::Long),
+            NoteType::Roll {
+                key: _,
+                length: _,
+                id: _,
+            } => Some(Self::Roll),
+            NoteType::AdLib { key: _ } => Some(Self::AdLib),
+            _ => None,
+        }
+    }
+}
+
+/// 判定幅の設定ファイルの内容
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JudgeConfigParser {
+    #[serde(default)]
+    pub profile: JudgePreset,
+    /// プリセットの代わりに使う判定幅
+    #[serde(default)]
+    pub window: Option<JudgeWindow>,
+    /// ノーツの種類ごとの判定幅の倍率
+    #[serde(default)]
+    pub note_types: HashMap<JudgeNoteKind, f64>,
+}
+impl JudgeConfigParser {
+    /// 判定幅として使えない値があればその内容を返す
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(window) = self.window.filter(|window| !window.is_valid()) {
+            return Err(format!(
+                "judge window must satisfy 0 < perfect <= near_perfect <= ok: {:?}",
+                window
+            ));
+        }
+        if let Some((kind, scale)) = self.note_types.iter().find(|(_, scale)| **scale <= 0.0) {
+            return Err(format!(
+                "scale of judge window for {:?} must be positive: {}",
+                kind, scale
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// ゲーム中に使う判定幅
+#[derive(Debug, Clone, Resource)]
+pub struct JudgeProfile {
+    /// スコアと一緒に記録する名前. プリセット以外の判定幅を指定した場合は`custom`となる.
+    pub name: String,
+    window: JudgeWindow,
+    note_types: HashMap<JudgeNoteKind, f64>,
+}
+impl JudgeProfile {
+    /// ノーツの種類ごとの倍率をかける前の判定幅
+    pub fn base_window(&self) -> JudgeWindow {
+        self.window
+    }
+
+    /// ノーツの種類に応じた判定幅
+    pub fn window(&self, note_type: &NoteType) -> JudgeWindow {
+        JudgeNoteKind::from_note_type(note_type)
+            .and_then(|kind| self.note_types.get(&kind))
+            .map_or(self.window, |scale| self.window.scaled(*scale))
+    }
+}
+impl Default for JudgeProfile {
+    fn default() -> Self {
+        JudgeProfile::from(JudgeConfigParser::default())
+    }
+}
+impl From<JudgeConfigParser> for JudgeProfile {
+    fn from(config: JudgeConfigParser) -> Self {
+        let (name, window) = match config.window {
+            Some(window) => ("custom".to_string(), window),
+            None => (config.profile.to_string(), config.profile.window()),
+        };
+        Self {
+            name,
+            window,
+            note_types: config.note_types,
+        }
+    }
+}
+
+#[test]
+fn judge_profile_test() {
+    let config =
+        serde_yaml::from_str::<JudgeConfigParser>("profile: strict\nnote_types:\n  AdLib: 2.0\n")
+            .unwrap();
+    assert!(config.validate().is_ok());
+    let profile = JudgeProfile::from(config);
+    assert_eq!(profile.name, "strict");
+
+    let normal = NoteType::Normal { key: 0 };
+    let adlib = NoteType::AdLib { key: 0 };
+    // 標準の判定幅ではPerfectでも, 厳しい判定幅では早いことになる
+    assert_eq!(
+        JudgeProfile::default().window(&normal).eval(1.0, 0.985),
+        CatchEval::Perfect
+    );
+    assert_eq!(
+        profile.window(&normal).eval(1.0, 0.985),
+        CatchEval::NearPerfect(TimingEval::Fast)
+    );
+    assert_eq!(profile.window(&normal).eval(1.0, 1.1), CatchEval::Miss);
+    // アドリブノーツは判定幅が2倍になる
+    assert_eq!(
+        profile.window(&adlib).eval(1.0, 1.1),
+        CatchEval::Ok(TimingEval::Slow)
+    );
+
+    let invalid = serde_yaml::from_str::<JudgeConfigParser>(
+        "window: { perfect: 0.05, near_perfect: 0.02, ok: 0.1 }",
+    )
+    .unwrap();
+    assert!(invalid.validate().is_err());
+}
@@ -0,0 +1,92 @@
+//! プレイ中のライフゲージ.
+//! ミスで減り, 取得で回復する. 空になるとその時点でゲームを終了する（ノーフェイルの場合は続行する）.
+
+use bevy::prelude::*;
+
+use super::{config::GaugeType, score::CatchEval};
+
+/// 増減を繰り返したときの誤差で空にならないことを防ぐため, これ以下の残量は空とみなす
+const EMPTY_EPSILON: f64 = 1e-9;
+
+/// ライフゲージ. 値は0〜1で, 満タンから始まる.
+#[derive(Resource, Debug)]
+pub struct LifeGauge {
+    gauge_type: GaugeType,
+    no_fail: bool,
+    value: f64,
+    /// 一度でもゲージが空になったか
+    emptied: bool,
+}
+impl LifeGauge {
+    pub fn new(gauge_type: GaugeType, no_fail: bool) -> Self {
+        Self {
+            gauge_type,
+            no_fail,
+            value: 1.0,
+            emptied: false,
+        }
+    }
+
+    /// 評価に応じてゲージを増減させる. 失敗が確定した後は変化しない.
+    pub fn update(&mut self, catch_eval: &CatchEval) {
+        if self.is_failed() {
+            return;
+        }
+        let delta = match catch_eval {
+            CatchEval::Perfect | CatchEval::NearPerfect(_) => self.gauge_type.recovery(),
+            CatchEval::Ok(_) => self.gauge_type.recovery() / 2.0,
+            CatchEval::Miss | CatchEval::Mine => -self.gauge_type.damage(),
+        };
+        self.value = (self.value + delta).clamp(0.0, 1.0);
+        if self.value <= EMPTY_EPSILON {
+            self.value = 0.0;
+            self.emptied = true;
+        }
+    }
+
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
+    pub fn get_gauge_type(&self) -> GaugeType {
+        self.gauge_type
+    }
+    pub fn is_no_fail(&self) -> bool {
+        self.no_fail
+    }
+
+    /// ゲージが空になってゲームを途中で終了するか
+    pub fn is_failed(&self) -> bool {
+        self.emptied && !self.no_fail
+    }
+
+    /// ノーフェイルでなければ失敗していたか
+    pub fn is_emptied(&self) -> bool {
+        self.emptied
+    }
+}
+
+#[test]
+fn life_gauge_test() {
+    let mut gauge = LifeGauge::new(GaugeType::Hard, false);
+    gauge.update(&CatchEval::Perfect);
+    assert_eq!(gauge.get_value(), 1.0);
+    for _ in 0..9 {
+        gauge.update(&CatchEval::Miss);
+    }
+    assert!(!gauge.is_failed());
+    gauge.update(&CatchEval::Mine);
+    assert!(gauge.is_failed());
+    // 失敗した後は回復しない
+    gauge.update(&CatchEval::Perfect);
+    assert_eq!(gauge.get_value(), 0.0);
+
+    let mut gauge = LifeGauge::new(GaugeType::Survival, true);
+    for _ in 0..30 {
+        gauge.update(&CatchEval::Miss);
+    }
+    assert!(gauge.is_emptied());
+    assert!(!gauge.is_failed());
+    // サバイバルゲージは回復しない
+    gauge.update(&CatchEval::Perfect);
+    assert_eq!(gauge.get_value(), 0.0);
+}
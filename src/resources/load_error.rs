@@ -0,0 +1,98 @@
+//! 曲一覧・譜面ファイルの読み込みで起きるエラー.
+//! パニックせずにエラー画面へ移行できるようにリソースとして保持する.
+
+use std::fmt;
+
+use bevy::prelude::*;
+use itertools::Itertools;
+
+use super::validation::ChartIssue;
+use crate::AppState;
+
+/// yamlのパースエラー. アセットとして保持できるようにメッセージと位置のみ持つ.
+#[derive(Debug, Clone)]
+pub struct YamlError {
+    pub message: String,
+    /// 行と列（1始まり）
+    pub location: Option<(usize, usize)>,
+}
+impl From<serde_yaml::Error> for YamlError {
+    fn from(e: serde_yaml::Error) -> Self {
+        Self {
+            message: e.to_string(),
+            location: e.location().map(|loc| (loc.line(), loc.column())),
+        }
+    }
+}
+
+/// 読み込みエラーの種類
+#[derive(Debug)]
+pub enum LoadErrorKind {
+    /// yamlとして解釈できない
+    Parse(YamlError),
+    /// 譜面の検査でエラーが見つかった
+    InvalidChart(Vec<ChartIssue>),
+    /// 曲一覧の内容がおかしい
+    InvalidSongList(String),
+    /// 判定幅の設定の内容がおかしい
+    InvalidJudgeConfig(String),
+    /// アセットサーバーによる読み込みに失敗した（ファイルが存在しない等）
+    Asset,
+}
+
+/// 読み込みエラー. どのファイルで起きたかを持つ.
+#[derive(Resource, Debug)]
+pub struct LoadError {
+    pub path: String,
+    pub kind: LoadErrorKind,
+    /// エラー画面から戻る先のステート
+    pub back_to: AppState,
+}
+impl LoadError {
+    pub fn new(path: impl Into<String>, kind: LoadErrorKind) -> Self {
+        Self {
+            path: path.into(),
+            kind,
+            back_to: AppState::SongSelect,
+        }
+    }
+
+    /// エラー画面から戻る先を指定する
+    pub fn with_back_to(self, back_to: AppState) -> Self {
+        Self { back_to, ..self }
+    }
+
+    /// yamlのエラーであれば行と列を返す
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match &self.kind {
+            LoadErrorKind::Parse(e) => e.location,
+            _ => None,
+        }
+    }
+}
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            LoadErrorKind::Parse(e) => {
+                if let Some((line, column)) = e.location {
+                    write!(f, "{}:{}:{}: {}", self.path, line, column, e.message)
+                } else {
+                    write!(f, "{}: {}", self.path, e.message)
+                }
+            }
+            LoadErrorKind::InvalidChart(issues) => write!(
+                f,
+                "{}: {} error(s) in chart\n{}",
+                self.path,
+                issues.len(),
+                issues.iter().join("\n")
+            ),
+            LoadErrorKind::InvalidSongList(message)
+            | LoadErrorKind::InvalidJudgeConfig(message) => {
+                write!(f, "{}: {}", self.path, message)
+            }
+            LoadErrorKind::Asset => write!(f, "{}: failed to load asset", self.path),
+        }
+    }
+}
+impl std::error::Error for LoadError {}
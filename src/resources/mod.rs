@@ -1,8 +1,17 @@
+pub mod analysis;
+pub mod asset_loader;
+pub mod beat_position;
 pub mod config;
 pub mod editor;
 pub mod game_state;
 pub mod handles;
+pub mod import;
+pub mod judge;
+pub mod life_gauge;
+pub mod load_error;
 pub mod note;
 pub mod score;
 pub mod song;
 pub mod song_list;
+pub mod timing;
+pub mod validation;
@@ -4,13 +4,18 @@
 
 use serde::{Deserialize, Serialize};
 
+use super::beat_position::BeatPosition;
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct NoteSpawnParser {
     note: NoteTypeParser,
     /// 小節番号（0始まり）
     bar: u32,
-    /// 小節内の拍位置（0始まり）. 例えば1.5なら2拍目の裏になる
-    beat: f64,
+    /// 小節内の拍位置（0始まり）. 例えば1.5なら2拍目の裏になる. 3連符は"1/3"のように分数でも書ける.
+    beat: BeatPosition,
+    /// ノーツを取得したときに鳴らすキー音. 譜面の`keysounds`の番号.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sound: Option<u32>,
 }
 
 /// YAMLファイルのノーツ情報パース用構造体
@@ -30,13 +35,38 @@ pub enum NoteTypeParser {
         /// 同一のノートであることを確かめられるように, イベント送信時に適当な数字を挿入する.
         id: u32,
     },
+    /// この位置以降のBPMを変更する
+    BpmChange {
+        bpm: f32,
+    },
+    /// この小節以降の一小節の拍数を変更する. 小節の頭（beat: 0）に置く.
+    BeatChange {
+        beat: u32,
+    },
+    /// この位置でキー音を鳴らす. soundは譜面の`keysounds`の番号.
+    /// ノーツの取得とは関係なく鳴るので, 伴奏に使う. ノーツを叩いた音はノーツの`sound`で指定する.
+    KeySound {
+        sound: u32,
+    },
+    /// 地雷. 判定線に来たときに鍵盤を押すと減点される.
+    Mine {
+        key: i32,
+    },
+    /// ロールノーツ. lenは拍数で指定. 押し続けるのではなく, 終点まで一定の拍数以内の間隔で叩き続ける.
+    /// idはロングノーツと共通の番号を使う.
+    Roll {
+        key: i32,
+        len: f32,
+        id: u32,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub struct NoteSpawn {
     pub note_type: NoteType,
     pub bar: u32,
-    pub beat: f64,
+    pub beat: BeatPosition,
+    pub sound: Option<u32>,
 }
 impl From<NoteSpawnParser> for NoteSpawn {
     fn from(data: NoteSpawnParser) -> Self {
@@ -44,6 +74,7 @@ impl From<NoteSpawnParser> for NoteSpawn {
             note_type: data.note.into(),
             bar: data.bar,
             beat: data.beat,
+            sound: data.sound,
         }
     }
 }
@@ -53,6 +84,7 @@ impl From<NoteSpawn> for NoteSpawnParser {
             note: data.note_type.into(),
             bar: data.bar,
             beat: data.beat,
+            sound: data.sound,
         }
     }
 }
@@ -69,6 +101,12 @@ pub enum NoteTypeKey {
     Long {
         key: i32,
     },
+    Mine {
+        key: i32,
+    },
+    Roll {
+        key: i32,
+    },
     /// 集計しないための列挙子
     Other,
 }
@@ -87,13 +125,19 @@ impl From<&NoteType> for NoteTypeKey {
                 length: _,
                 id: _,
             } => NoteTypeKey::Long { key: *key },
+            NoteType::Mine { key } => NoteTypeKey::Mine { key: *key },
+            NoteType::Roll {
+                key,
+                length: _,
+                id: _,
+            } => NoteTypeKey::Roll { key: *key },
             _ => NoteTypeKey::Other,
         }
     }
 }
 
 /// ノーツの種類ごとの情報を保持する構造体.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum NoteType {
     Normal {
         key: i32,
@@ -108,6 +152,28 @@ pub enum NoteType {
         length: f32,
         id: u32,
     },
+    /// BPM変更イベント. 判定線に到達した時点でBPMを切り替える. 見えず, 取得もできない.
+    BpmChange {
+        bpm: f32,
+    },
+    /// 拍子変更イベント. 置かれた小節から一小節の拍数を切り替える.
+    BeatChange {
+        beat: u32,
+    },
+    /// キー音イベント. 判定線に到達した時点で音を鳴らす. 見えず, 取得もできない.
+    KeySound {
+        sound: u32,
+    },
+    /// 地雷ノーツ. 避けるべきノーツで, 取得すると減点される. 見送ってもミスにならない.
+    Mine {
+        key: i32,
+    },
+    /// ロールノーツ. ロングノーツと同様に扱うが, 押し続ける代わりに叩き続ける必要がある.
+    Roll {
+        key: i32,
+        length: f32,
+        id: u32,
+    },
 }
 impl From<NoteTypeParser> for NoteType {
     fn from(data: NoteTypeParser) -> Self {
@@ -120,6 +186,15 @@ impl From<NoteTypeParser> for NoteType {
                 length: len,
                 id,
             },
+            NoteTypeParser::BpmChange { bpm } => NoteType::BpmChange { bpm },
+            NoteTypeParser::BeatChange { beat } => NoteType::BeatChange { beat },
+            NoteTypeParser::KeySound { sound } => NoteType::KeySound { sound },
+            NoteTypeParser::Mine { key } => NoteType::Mine { key },
+            NoteTypeParser::Roll { key, len, id } => NoteType::Roll {
+                key,
+                length: len,
+                id,
+            },
         }
     }
 }
@@ -134,6 +209,15 @@ impl From<NoteType> for NoteTypeParser {
                 len: length,
                 id,
             },
+            NoteType::BpmChange { bpm } => NoteTypeParser::BpmChange { bpm },
+            NoteType::BeatChange { beat } => NoteTypeParser::BeatChange { beat },
+            NoteType::KeySound { sound } => NoteTypeParser::KeySound { sound },
+            NoteType::Mine { key } => NoteTypeParser::Mine { key },
+            NoteType::Roll { key, length, id } => NoteTypeParser::Roll {
+                key,
+                len: length,
+                id,
+            },
         }
     }
 }
@@ -143,13 +227,15 @@ fn yaml_test() {
     let y = vec![
         NoteSpawnParser {
             bar: 0,
-            beat: 0.0,
+            beat: BeatPosition::ZERO,
             note: NoteTypeParser::Normal { key: 0 },
+            sound: None,
         },
         NoteSpawnParser {
             bar: 0,
-            beat: 0.5,
+            beat: BeatPosition::new(1, 2),
             note: NoteTypeParser::Normal { key: 1 },
+            sound: Some(0),
         },
     ];
     println!("{}", serde_yaml::to_string(&y).unwrap());
@@ -1,9 +1,19 @@
 use bevy::{prelude::*, utils::HashMap};
 
-use crate::{components::receptor::NotesPattern, constants::MISS_THR};
+use crate::components::{
+    note::{LongNote, NoteInfo},
+    receptor::NotesPattern,
+};
 
 use super::note::{NoteType, NoteTypeKey};
 
+/// すべてPerfectで取得したときのスコア. ノーツ数によらずこの値になるよう換算する.
+pub const MAX_NOTE_SCORE: u32 = 1_000_000;
+/// 配置パターン一つ分の加点（パターンの`to_score`倍する）
+pub const PATTERN_BONUS_UNIT: u32 = 1_000;
+/// 配置パターンによる加点の上限
+pub const MAX_PATTERN_BONUS: u32 = 100_000;
+
 /// Perfect以外は遅いか早いかをもたせる
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum TimingEval {
@@ -31,6 +41,17 @@ impl TimingEval {
     }
 }
 
+/// 評価の対象がノーツのどの部分か
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum JudgePoint {
+    /// 通常ノーツ, またはロングノーツ・ロールノーツの始点
+    Head,
+    /// ロングノーツ・ロールノーツの終点
+    Tail,
+    /// ロングノーツ・ロールノーツを押している間の加点
+    Tick,
+}
+
 /// ノーツ取得の評価
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum CatchEval {
@@ -38,6 +59,8 @@ pub enum CatchEval {
     NearPerfect(TimingEval),
     Ok(TimingEval),
     Miss,
+    /// 地雷を踏んだ
+    Mine,
 }
 impl std::fmt::Display for CatchEval {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -54,33 +77,32 @@ impl std::fmt::Display for CatchEval {
             CatchEval::Miss => {
                 write!(f, "miss")
             }
+            CatchEval::Mine => {
+                write!(f, "mine")
+            }
         }
     }
 }
 impl CatchEval {
-    pub fn new(target_time: f64, real_time: f64) -> Self {
-        match real_time - target_time {
-            // この分岐は起こらないはず
-            diff if diff < -MISS_THR => Self::Miss,
-            diff if (-MISS_THR..=-MISS_THR / 3.0).contains(&diff) => Self::Ok(TimingEval::Fast),
-            diff if (-MISS_THR / 3.0..=-MISS_THR / 6.0).contains(&diff) => {
-                Self::NearPerfect(TimingEval::Fast)
-            }
-            diff if (MISS_THR / 6.0..=MISS_THR / 3.0).contains(&diff) => {
-                Self::NearPerfect(TimingEval::Slow)
-            }
-            diff if (MISS_THR / 3.0..=MISS_THR).contains(&diff) => Self::Ok(TimingEval::Slow),
-            diff if diff > MISS_THR => Self::Miss,
-            _ => Self::Perfect,
-        }
-    }
-
-    pub fn as_score(&self) -> u32 {
+    /// 評価による加点. 地雷を踏むと減点になる.
+    pub fn as_score(&self) -> i32 {
         match self {
             CatchEval::Perfect => 2,
             CatchEval::NearPerfect(_) => 2,
             CatchEval::Ok(_) => 1,
             CatchEval::Miss => 0,
+            CatchEval::Mine => -2,
+        }
+    }
+
+    /// 精度を求めるときの重み. 地雷は精度に含めないのでNone.
+    pub fn accuracy_weight(&self) -> Option<f64> {
+        match self {
+            CatchEval::Perfect => Some(1.0),
+            CatchEval::NearPerfect(_) => Some(0.9),
+            CatchEval::Ok(_) => Some(0.5),
+            CatchEval::Miss => Some(0.0),
+            CatchEval::Mine => None,
         }
     }
 
@@ -90,6 +112,7 @@ impl CatchEval {
             CatchEval::NearPerfect(_) => Color::GOLD,
             CatchEval::Ok(_) => Color::GREEN,
             CatchEval::Miss => Color::GRAY,
+            CatchEval::Mine => Color::PURPLE,
         }
     }
     pub fn get_timing(&self) -> Option<TimingEval> {
@@ -98,14 +121,99 @@ impl CatchEval {
             CatchEval::NearPerfect(timing) => Some(timing),
             CatchEval::Ok(timing) => Some(timing),
             CatchEval::Miss => None,
+            CatchEval::Mine => None,
         }
         .cloned()
     }
 }
 
+/// 精度から決まる評価. 譜面の長さによらないので曲をまたいで比べられる.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Grade {
+    D,
+    C,
+    B,
+    A,
+    S,
+    SS,
+    SSS,
+}
+impl Grade {
+    /// 精度（0〜1）から評価を決める
+    pub fn from_accuracy(accuracy: f64) -> Self {
+        match accuracy {
+            a if a >= 0.99 => Grade::SSS,
+            a if a >= 0.97 => Grade::SS,
+            a if a >= 0.95 => Grade::S,
+            a if a >= 0.9 => Grade::A,
+            a if a >= 0.8 => Grade::B,
+            a if a >= 0.7 => Grade::C,
+            _ => Grade::D,
+        }
+    }
+
+    pub fn get_color(&self) -> Color {
+        match self {
+            Grade::SSS | Grade::SS | Grade::S => Color::GOLD,
+            Grade::A => Color::ORANGE_RED,
+            Grade::B => Color::GREEN,
+            Grade::C => Color::BLUE,
+            Grade::D => Color::GRAY,
+        }
+    }
+}
+impl std::fmt::Display for Grade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// 譜面のノーツをすべてPerfectで取得したときの評価による加点の合計.
+/// ロングノーツ・ロールノーツは始点, 押している間, 終点のそれぞれで評価される.
+/// アドリブノーツは取らなくてもよいので数えない.
+pub fn max_raw_score(notes: &[NoteInfo]) -> u32 {
+    let judge_num = notes
+        .iter()
+        .map(|note| match note.note_type {
+            NoteType::Normal { key: _ } => 1,
+            NoteType::Long {
+                key: _,
+                length,
+                id: _,
+            }
+            | NoteType::Roll {
+                key: _,
+                length,
+                id: _,
+            } => 2 + LongNote::tick_num(length),
+            _ => 0,
+        })
+        .sum::<u32>();
+    judge_num * CatchEval::Perfect.as_score() as u32
+}
+
+/// 叩いた時刻の誤差の統計. 時間の単位は秒.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimingStats {
+    /// 誤差の平均. 正なら全体的に遅れている.
+    pub mean: f64,
+    /// 誤差の標準偏差
+    pub std_dev: f64,
+    /// 早いと評価された数
+    pub fast: u32,
+    /// 遅いと評価された数
+    pub slow: u32,
+}
+
 #[derive(Default, Debug, Resource)]
 pub struct ScoreResource {
+    /// 評価による加点の合計. 表示するスコアはこれを換算したもの.
     score: usize,
+    /// 譜面の評価による加点の最大値
+    max_raw_score: u32,
+    /// 配置パターンによる加点の合計
+    pattern_score: u32,
 
     /// ノーツタイプごとに取得数を保存しておく配列
     note_type_storage: HashMap<NoteTypeKey, u32>,
@@ -113,10 +221,40 @@ pub struct ScoreResource {
     pattern_vec: Vec<NotesPattern>,
     /// 取得評価を保存しておく. 評価列挙型に`Hash`を実装することでキーとして使えるようにしている.
     eval_storage: HashMap<CatchEval, u32>,
+    /// 精度に使う評価. アドリブ以外のノーツの始点と終点の評価のみで, 押している間の加点は含めない.
+    accuracy_storage: HashMap<CatchEval, u32>,
+    /// ロングノーツ・ロールノーツの始点と終点の評価を分けて保存しておく
+    long_note_storage: HashMap<(JudgePoint, CatchEval), u32>,
+    /// プレイに使った判定幅の名前
+    judge_profile: String,
+    /// 叩いた時刻の誤差. ノーツの目標時刻と誤差（秒）の組で, 評価された順に並ぶ.
+    timing_errors: Vec<(f64, f64)>,
+    /// 誤差を記録したノーツのうち, 早い・遅いと評価された数
+    timing_storage: HashMap<TimingEval, u32>,
+    /// 現在のコンボ数
+    combo: u32,
+    /// プレイ中の最大コンボ数
+    max_combo: u32,
 }
 impl ScoreResource {
+    /// 譜面の評価による加点の最大値と, 判定幅の名前を記録したスコアを用意する
+    pub fn new(max_raw_score: u32, judge_profile: &str) -> Self {
+        Self {
+            max_raw_score,
+            judge_profile: judge_profile.to_string(),
+            ..default()
+        }
+    }
+
     /// 取得数を増やし, スコアを増加させる.
-    pub fn update_score(&mut self, catch_eval: &CatchEval, ty: &NoteType) {
+    pub fn update_score(&mut self, catch_eval: &CatchEval, ty: &NoteType, point: JudgePoint) {
+        let is_long = matches!(ty, NoteType::Long { .. } | NoteType::Roll { .. });
+        if is_long && point != JudgePoint::Tick {
+            *self
+                .long_note_storage
+                .entry((point, *catch_eval))
+                .or_insert(0) += 1;
+        }
         if let Some(prev_val) = self.eval_storage.get_mut(catch_eval) {
             *prev_val += 1;
         } else {
@@ -129,15 +267,75 @@ impl ScoreResource {
             self.note_type_storage.insert(ty_key, 1);
         }
 
-        self.score += catch_eval.as_score() as usize;
+        // アドリブノーツは満点の計算に含めないので評価による加点もしない.
+        // 取得したアドリブノーツは配置パターンのボーナスとしてのみスコアに加わる.
+        if matches!(ty, NoteType::AdLib { key: _ }) {
+            return;
+        }
+        // 押している間の加点は数が長さで決まるので, 精度はノーツごとの評価だけで求める
+        if point != JudgePoint::Tick {
+            *self.accuracy_storage.entry(*catch_eval).or_insert(0) += 1;
+        }
+        // スコアは負にならないようにする
+        self.score = self
+            .score
+            .saturating_add_signed(catch_eval.as_score() as isize);
     }
 
-    pub fn add_score(&mut self, score: u32) {
-        self.score += score as usize;
+    /// コンボを更新する. ミスか地雷を踏むとコンボが途切れる.
+    /// ロングノーツ・ロールノーツを押している間の加点もコンボに数え, 途中で離すと途切れる.
+    /// アドリブノーツは取らなくてもよいのでコンボには数えない.
+    pub fn update_combo(&mut self, catch_eval: &CatchEval, ty: &NoteType) {
+        if matches!(ty, NoteType::AdLib { key: _ }) {
+            return;
+        }
+        match catch_eval {
+            CatchEval::Miss | CatchEval::Mine => self.combo = 0,
+            _ => {
+                self.combo += 1;
+                self.max_combo = self.max_combo.max(self.combo);
+            }
+        }
+    }
+
+    pub fn get_combo(&self) -> u32 {
+        self.combo
+    }
+    pub fn get_max_combo(&self) -> u32 {
+        self.max_combo
+    }
+
+    /// 一度もコンボが途切れずに最後まで取得したか
+    pub fn is_full_combo(&self) -> bool {
+        self.max_combo > 0
+            && self.get_eval_num(&CatchEval::Miss) == 0
+            && self.get_eval_num(&CatchEval::Mine) == 0
+    }
+
+    /// フルコンボかつすべてPerfect（ズレありを含む）で取得したか
+    pub fn is_all_perfect(&self) -> bool {
+        self.is_full_combo()
+            && self.get_eval_num(&CatchEval::Ok(TimingEval::Fast)) == 0
+            && self.get_eval_num(&CatchEval::Ok(TimingEval::Slow)) == 0
+    }
+
+    /// 評価による加点を`MAX_NOTE_SCORE`を満点として換算したもの
+    pub fn get_note_score(&self) -> u32 {
+        if self.max_raw_score == 0 {
+            return 0;
+        }
+        let ratio = (self.score as f64 / self.max_raw_score as f64).min(1.0);
+        (ratio * MAX_NOTE_SCORE as f64).round() as u32
     }
 
-    pub fn get_score(&self) -> usize {
-        self.score
+    /// 配置パターンによる加点. `MAX_PATTERN_BONUS`を上限とする.
+    pub fn get_pattern_bonus(&self) -> u32 {
+        (self.pattern_score * PATTERN_BONUS_UNIT).min(MAX_PATTERN_BONUS)
+    }
+
+    /// 換算したスコアと配置パターンによる加点の合計
+    pub fn get_score(&self) -> u32 {
+        self.get_note_score() + self.get_pattern_bonus()
     }
     pub fn get_eval_num(&self, key: &CatchEval) -> u32 {
         if let Some(res) = self.eval_storage.get(key) {
@@ -149,12 +347,100 @@ impl ScoreResource {
 
     pub fn push_pattern(&mut self, pattern: NotesPattern) {
         self.pattern_vec.push(pattern);
-        self.add_score(pattern.to_score());
+        self.pattern_score += pattern.to_score();
+    }
+
+    /// ロングノーツ・ロールノーツの始点または終点での評価の数
+    pub fn get_long_note_eval_num(&self, point: JudgePoint, key: &CatchEval) -> u32 {
+        self.long_note_storage
+            .get(&(point, *key))
+            .copied()
+            .unwrap_or(0)
     }
 
-    pub fn get_eval_storage(&self) -> &HashMap<CatchEval, u32> {
-        &self.eval_storage
+    /// ロングノーツ・ロールノーツの始点と終点の評価から求めた精度（0〜1）. 一つもなければNone.
+    pub fn get_hold_accuracy(&self) -> Option<f64> {
+        let (score, num) = self
+            .long_note_storage
+            .iter()
+            .fold((0, 0), |(score, num), ((_, eval), n)| {
+                (score + eval.as_score().max(0) as u32 * n, num + n)
+            });
+        (num > 0).then(|| score as f64 / (num * CatchEval::Perfect.as_score() as u32) as f64)
+    }
+
+    pub fn get_judge_profile(&self) -> &str {
+        &self.judge_profile
+    }
+
+    /// アドリブ以外のノーツの始点と終点の評価ごとの重みから求めた精度（0〜1）. まだ何も評価されていなければNone.
+    pub fn get_accuracy(&self) -> Option<f64> {
+        let (weight, num) = self
+            .accuracy_storage
+            .iter()
+            .filter_map(|(eval, n)| eval.accuracy_weight().map(|weight| (weight, *n)))
+            .fold((0.0, 0), |(sum, num), (weight, n)| {
+                (sum + weight * n as f64, num + n)
+            });
+        (num > 0).then(|| weight / num as f64)
+    }
+
+    /// 精度から決まる評価. まだ何も評価されていなければNone.
+    pub fn get_grade(&self) -> Option<Grade> {
+        self.get_accuracy().map(Grade::from_accuracy)
+    }
+
+    /// 叩いた時刻の誤差とその評価を記録する
+    pub fn record_timing(&mut self, target_time: f64, timing_error: f64, catch_eval: &CatchEval) {
+        self.timing_errors.push((target_time, timing_error));
+        if let Some(timing) = catch_eval.get_timing() {
+            *self.timing_storage.entry(timing).or_insert(0) += 1;
+        }
     }
+
+    pub fn get_timing_errors(&self) -> &Vec<(f64, f64)> {
+        &self.timing_errors
+    }
+
+    /// 誤差の平均と標準偏差, 早い・遅いの数. 誤差が一つも記録されていなければNone.
+    /// いずれも誤差を記録したノーツだけから求めるので, ロングノーツの終点の評価は含まない.
+    pub fn get_timing_stats(&self) -> Option<TimingStats> {
+        if self.timing_errors.is_empty() {
+            return None;
+        }
+        let num = self.timing_errors.len() as f64;
+        let mean = self.timing_errors.iter().map(|(_, e)| e).sum::<f64>() / num;
+        let variance = self
+            .timing_errors
+            .iter()
+            .map(|(_, e)| (e - mean).powi(2))
+            .sum::<f64>()
+            / num;
+        let count = |timing: TimingEval| self.timing_storage.get(&timing).copied().unwrap_or(0);
+        Some(TimingStats {
+            mean,
+            std_dev: variance.sqrt(),
+            fast: count(TimingEval::Fast),
+            slow: count(TimingEval::Slow),
+        })
+    }
+
+    /// `-range..=range`の誤差を`bin_num`個の区間に分けて数える. 範囲外の誤差は端の区間に含める.
+    pub fn get_timing_histogram(&self, range: f64, bin_num: usize) -> Vec<u32> {
+        let mut bins = vec![0; bin_num];
+        if bin_num == 0 {
+            return bins;
+        }
+        for (_, error) in self.timing_errors.iter() {
+            let ratio = (error + range) / (2.0 * range);
+            let idx = (ratio * bin_num as f64)
+                .floor()
+                .clamp(0.0, (bin_num - 1) as f64);
+            bins[idx as usize] += 1;
+        }
+        bins
+    }
+
     pub fn get_pattern_vec(&self) -> &Vec<NotesPattern> {
         &self.pattern_vec
     }
@@ -162,3 +448,196 @@ impl ScoreResource {
         &self.note_type_storage
     }
 }
+
+#[test]
+fn hold_accuracy_test() {
+    let long = NoteType::Long {
+        key: 0,
+        length: 1.0,
+        id: 1,
+    };
+    let mut score = ScoreResource::default();
+    assert_eq!(score.get_hold_accuracy(), None);
+
+    score.update_score(
+        &CatchEval::Perfect,
+        &NoteType::Normal { key: 0 },
+        JudgePoint::Head,
+    );
+    score.update_score(&CatchEval::Perfect, &long, JudgePoint::Head);
+    score.update_score(&CatchEval::Perfect, &long, JudgePoint::Tick);
+    score.update_score(&CatchEval::Ok(TimingEval::Fast), &long, JudgePoint::Tail);
+    // 通常ノーツと押している間の加点は含めない
+    assert_eq!(score.get_hold_accuracy(), Some(0.75));
+    assert_eq!(
+        score.get_long_note_eval_num(JudgePoint::Tail, &CatchEval::Ok(TimingEval::Fast)),
+        1
+    );
+    assert_eq!(score.get_eval_num(&CatchEval::Perfect), 3);
+}
+
+#[test]
+fn combo_test() {
+    let normal = NoteType::Normal { key: 0 };
+    let mut score = ScoreResource::default();
+    assert!(!score.is_full_combo());
+
+    for eval in [
+        CatchEval::Perfect,
+        CatchEval::NearPerfect(TimingEval::Slow),
+        CatchEval::Perfect,
+    ] {
+        score.update_score(&eval, &normal, JudgePoint::Head);
+        score.update_combo(&eval, &normal);
+    }
+    assert_eq!(score.get_combo(), 3);
+    assert!(score.is_all_perfect());
+
+    score.update_score(&CatchEval::Ok(TimingEval::Fast), &normal, JudgePoint::Head);
+    score.update_combo(&CatchEval::Ok(TimingEval::Fast), &normal);
+    assert!(score.is_full_combo());
+    assert!(!score.is_all_perfect());
+
+    score.update_score(&CatchEval::Miss, &normal, JudgePoint::Head);
+    score.update_combo(&CatchEval::Miss, &normal);
+    score.update_score(&CatchEval::Perfect, &normal, JudgePoint::Head);
+    score.update_combo(&CatchEval::Perfect, &normal);
+    // アドリブノーツはコンボに数えない
+    let adlib = NoteType::AdLib { key: 0 };
+    score.update_score(&CatchEval::Perfect, &adlib, JudgePoint::Head);
+    score.update_combo(&CatchEval::Perfect, &adlib);
+    assert_eq!(score.get_combo(), 1);
+    assert_eq!(score.get_max_combo(), 4);
+    assert!(!score.is_full_combo());
+}
+
+#[test]
+fn accuracy_test() {
+    let normal = NoteType::Normal { key: 0 };
+    let mut score = ScoreResource::default();
+    assert_eq!(score.get_grade(), None);
+
+    for eval in [
+        CatchEval::Perfect,
+        CatchEval::NearPerfect(TimingEval::Fast),
+        CatchEval::Ok(TimingEval::Slow),
+        CatchEval::Miss,
+        // 地雷は精度に含めない
+        CatchEval::Mine,
+    ] {
+        score.update_score(&eval, &normal, JudgePoint::Head);
+    }
+    assert_eq!(score.get_accuracy(), Some(0.6));
+    assert_eq!(score.get_grade(), Some(Grade::D));
+    assert_eq!(Grade::from_accuracy(1.0), Grade::SSS);
+    assert_eq!(Grade::from_accuracy(0.955), Grade::S);
+}
+
+#[test]
+fn hold_accuracy_excludes_ticks_test() {
+    let long = NoteType::Long {
+        key: 0,
+        length: 8.0,
+        id: 1,
+    };
+    let mut score = ScoreResource::default();
+    // 始点を取り逃しても押し続ければ加点はPerfectになるが, 精度には含めない
+    score.update_score(&CatchEval::Miss, &long, JudgePoint::Head);
+    for _ in 0..15 {
+        score.update_score(&CatchEval::Perfect, &long, JudgePoint::Tick);
+    }
+    score.update_score(&CatchEval::Perfect, &long, JudgePoint::Tail);
+    // 取得したアドリブノーツも精度には含めない
+    score.update_score(
+        &CatchEval::Perfect,
+        &NoteType::AdLib { key: 1 },
+        JudgePoint::Head,
+    );
+    assert_eq!(score.get_accuracy(), Some(0.5));
+    assert!(score.get_grade().unwrap() < Grade::S);
+}
+
+#[test]
+fn normalized_score_test() {
+    use crate::resources::beat_position::BeatPosition;
+
+    let note = |note_type: NoteType| NoteInfo {
+        note_type,
+        bar: 0,
+        beat: BeatPosition::ZERO,
+        spawn_time: 0.0,
+        target_time: 0.0,
+        end_time: 0.0,
+        tick_times: vec![],
+        bpm: 120.0,
+        sound: None,
+    };
+    let long = NoteType::Long {
+        key: 1,
+        length: 1.0,
+        id: 1,
+    };
+    let notes = [
+        note(NoteType::Normal { key: 0 }),
+        // 始点, 半拍後の加点, 終点
+        note(long.clone()),
+        note(NoteType::Mine { key: 2 }),
+        note(NoteType::BarLine),
+    ];
+    assert_eq!(max_raw_score(&notes), 8);
+
+    let mut score = ScoreResource::new(max_raw_score(&notes), "standard");
+    score.update_score(&CatchEval::Perfect, &notes[0].note_type, JudgePoint::Head);
+    score.update_score(&CatchEval::Perfect, &long, JudgePoint::Head);
+    score.update_score(&CatchEval::Perfect, &long, JudgePoint::Tick);
+    score.update_score(&CatchEval::Ok(TimingEval::Slow), &long, JudgePoint::Tail);
+    assert_eq!(score.get_note_score(), 875_000);
+
+    score.push_pattern(NotesPattern::Denim);
+    assert_eq!(score.get_pattern_bonus(), 2 * PATTERN_BONUS_UNIT);
+    assert_eq!(score.get_score(), 875_000 + 2 * PATTERN_BONUS_UNIT);
+    for _ in 0..100 {
+        score.push_pattern(NotesPattern::Denim);
+    }
+    assert_eq!(score.get_pattern_bonus(), MAX_PATTERN_BONUS);
+
+    // アドリブノーツを取らなくても, それ以外をすべてPerfectで取れば満点になる
+    let adlib = NoteType::AdLib { key: 3 };
+    let notes = [note(NoteType::Normal { key: 0 }), note(adlib.clone())];
+    let mut score = ScoreResource::new(max_raw_score(&notes), "standard");
+    score.update_score(&CatchEval::Perfect, &notes[0].note_type, JudgePoint::Head);
+    assert_eq!(score.get_score(), MAX_NOTE_SCORE);
+    score.update_score(&CatchEval::Perfect, &adlib, JudgePoint::Head);
+    assert_eq!(score.get_score(), MAX_NOTE_SCORE);
+}
+
+#[test]
+fn timing_stats_test() {
+    let normal = NoteType::Normal { key: 0 };
+    let mut score = ScoreResource::default();
+    assert_eq!(score.get_timing_stats(), None);
+
+    for (target_time, error, eval) in [
+        (1.0, -0.02, CatchEval::NearPerfect(TimingEval::Fast)),
+        (2.0, 0.0, CatchEval::Perfect),
+        (3.0, 0.05, CatchEval::Ok(TimingEval::Slow)),
+        (4.0, 0.01, CatchEval::Perfect),
+    ] {
+        score.update_score(&eval, &normal, JudgePoint::Head);
+        score.record_timing(target_time, error, &eval);
+    }
+    // ロングノーツの終点の評価は誤差を記録しないので, 早い・遅いの数にも含めない
+    let long = NoteType::Long {
+        key: 1,
+        length: 1.0,
+        id: 0,
+    };
+    score.update_score(&CatchEval::Ok(TimingEval::Slow), &long, JudgePoint::Tail);
+    let stats = score.get_timing_stats().unwrap();
+    assert!((stats.mean - 0.01).abs() < 1e-9);
+    assert!((stats.std_dev - 0.0255f64).abs() < 1e-3);
+    assert_eq!((stats.fast, stats.slow), (1, 1));
+    // 範囲外の誤差は端に入る
+    score.record_timing(5.0, 0.5, &CatchEval::Miss);
+    assert_eq!(score.get_timing_histogram(0.1, 4), vec![0, 1, 2, 2]);
+}
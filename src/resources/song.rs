@@ -4,7 +4,7 @@ use bevy::prelude::*;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use crate::components::note::NoteInfo;
+use crate::components::note::{KeyLane, NoteInfo};
 
 use super::note::{NoteSpawn, NoteSpawnParser};
 
@@ -26,7 +26,20 @@ pub struct SongConfigParser {
     /// 曲開始時点で一小節に何拍あるか
     pub initial_beat: u32,
     pub initial_bpm: f32,
+    /// 音源の再生開始から0小節目の頭までの時間（ミリ秒）. 音源の先頭の無音を補正する.
+    #[serde(default)]
+    pub offset: f64,
+    /// 鍵盤の数. 省略時は4鍵.
+    #[serde(default = "default_lane_num")]
+    pub lane_num: u8,
     pub notes: Vec<NoteSpawnParser>,
+    /// キー音のファイル名（songsからの相対パス）. `KeySound`ノーツから番号で指定する.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keysounds: Vec<String>,
+}
+
+fn default_lane_num() -> u8 {
+    KeyLane::DEFAULT_KEY_NUM
 }
 
 #[derive(Debug, Clone)]
@@ -38,7 +51,13 @@ pub struct SongConfig {
     /// 曲開始時点で一小節に何拍あるか
     pub initial_beat: u32,
     pub initial_bpm: f32,
+    /// 音源の再生開始から0小節目の頭までの時間（ミリ秒）
+    pub offset: f64,
+    /// 鍵盤の数
+    pub lane_num: u8,
     pub notes: Vec<NoteSpawn>,
+    /// キー音のファイル名
+    pub keysounds: Vec<String>,
 }
 impl From<SongConfigParser> for SongConfig {
     fn from(data: SongConfigParser) -> Self {
@@ -48,8 +67,11 @@ impl From<SongConfigParser> for SongConfig {
             length: data.length,
             initial_beat: data.initial_beat,
             initial_bpm: data.initial_bpm,
+            offset: data.offset,
+            lane_num: data.lane_num,
             // map(NoteSpawn::from)でも動く
             notes: data.notes.into_iter().map(|note| note.into()).collect_vec(),
+            keysounds: data.keysounds,
         }
     }
 }
@@ -61,7 +83,10 @@ impl From<SongConfig> for SongConfigParser {
             length: data.length,
             initial_beat: data.initial_beat,
             initial_bpm: data.initial_bpm,
+            offset: data.offset,
+            lane_num: data.lane_num,
             notes: data.notes.into_iter().map(|note| note.into()).collect_vec(),
+            keysounds: data.keysounds,
         }
     }
 }
@@ -73,6 +98,10 @@ pub struct SongConfigResource {
     pub song_filename: String,
     /// 曲の尺（秒）
     pub length: f64,
+    /// 音源の再生開始から0小節目の頭までの時間（秒）
+    pub offset: f64,
+    /// キー音のファイル名
+    pub keysounds: Vec<String>,
 }
 impl From<SongConfig> for SongConfigResource {
     fn from(config: SongConfig) -> Self {
@@ -80,6 +109,8 @@ impl From<SongConfig> for SongConfigResource {
             name: config.name,
             song_filename: config.filename,
             length: config.length,
+            offset: config.offset / 1000.0,
+            keysounds: config.keysounds,
         }
     }
 }
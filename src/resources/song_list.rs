@@ -1,31 +1,151 @@
-use bevy::prelude::{Component, Resource};
+use bevy::prelude::{Component, Deref, DerefMut, Handle, Resource};
+use bevy_kira_audio::AudioSource;
 use serde_derive::Deserialize;
 
-#[derive(Deserialize, Debug)]
+use super::{analysis::analyze_chart, note::NoteType, song::SongConfig};
+
+/// 一曲に含まれる譜面一つ分の情報
+#[derive(Deserialize, Debug, Clone)]
+pub struct ChartDataParser {
+    /// 譜面の名前（Easy, Hard, Another等）
+    pub name: String,
+    /// 譜面の難しさを表すレベル
+    pub level: u32,
+    pub config_file_name: String,
+    /// 譜面ごとに試聴を始める位置を変える場合に書く. 省略すると曲の`preview_start`を使う.
+    #[serde(default)]
+    pub preview_start: Option<f64>,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct ChartData {
+    pub name: String,
+    pub level: u32,
+    pub config_file_name: String,
+    pub preview_start: Option<f64>,
+    /// 譜面ファイルから求めた情報. 選曲画面で譜面が読み込まれるたびに埋め直し, 読めない譜面ではNoneになる.
+    pub summary: Option<ChartSummary>,
+}
+impl From<ChartDataParser> for ChartData {
+    fn from(data: ChartDataParser) -> Self {
+        Self {
+            name: data.name,
+            level: data.level,
+            config_file_name: data.config_file_name,
+            preview_start: data.preview_start,
+            summary: None,
+        }
+    }
+}
+
+/// 選曲画面に表示する, 譜面の中身から求まる情報
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartSummary {
+    pub min_bpm: f32,
+    pub max_bpm: f32,
+    /// 曲の尺（秒）
+    pub length: f64,
+    /// 音源のファイル名. 試聴に使う.
+    pub music_filename: String,
+    /// 譜面の分析から見積もった難しさ
+    pub rating: f64,
+}
+impl ChartSummary {
+    /// BPMの範囲を`120`や`120-180`の形式で返す
+    pub fn bpm_text(&self) -> String {
+        if self.min_bpm == self.max_bpm {
+            format!("{}", self.min_bpm)
+        } else {
+            format!("{}-{}", self.min_bpm, self.max_bpm)
+        }
+    }
+
+    /// 尺を`分:秒`の形式で返す
+    pub fn length_text(&self) -> String {
+        let secs = self.length.max(0.0).round() as u64;
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+impl From<&SongConfig> for ChartSummary {
+    fn from(config: &SongConfig) -> Self {
+        let bpms = config
+            .notes
+            .iter()
+            .filter_map(|note| match note.note_type {
+                NoteType::BpmChange { bpm } => Some(bpm),
+                _ => None,
+            })
+            .chain(std::iter::once(config.initial_bpm));
+        let (min_bpm, max_bpm) = bpms
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), bpm| {
+                (min.min(bpm), max.max(bpm))
+            });
+        Self {
+            min_bpm,
+            max_bpm,
+            length: config.length,
+            music_filename: config.filename.clone(),
+            rating: analyze_chart(config).rating,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct SongDataParser {
     pub name: String,
     /// 画像名で指定
     pub thumbnail: String,
-    pub config_file_name: String,
+    /// 譜面の一覧. 難易度の低い順に並べる. 空にはできない.
+    pub charts: Vec<ChartDataParser>,
     /// エディットモードで扱えるかどうかのフラグ. 立てなくても良いようにOption付き.
     /// Noneはtrueとして扱い, trueなら編集不可とする. 編集可能にする場合falseにする.
     pub edit_freeze: Option<bool>,
+    /// 作曲者
+    #[serde(default)]
+    pub artist: String,
+    /// 譜面の作者
+    #[serde(default)]
+    pub charter: String,
+    #[serde(default)]
+    pub genre: String,
+    /// 選曲画面で試聴を始める位置（音源の先頭からの秒数）
+    #[serde(default)]
+    pub preview_start: f64,
 }
 
 #[derive(Resource, Component, Debug, Clone)]
 pub struct SongData {
     pub name: String,
     pub thumbnail: String,
-    pub config_file_name: String,
+    pub charts: Vec<ChartData>,
     pub edit_freeze: bool,
+    pub artist: String,
+    pub charter: String,
+    pub genre: String,
+    pub preview_start: f64,
+}
+impl SongData {
+    /// 選択中の譜面番号に対応する譜面を返す. 譜面数を超える番号なら最後の譜面とする.
+    pub fn chart(&self, idx: usize) -> &ChartData {
+        &self.charts[idx.min(self.charts.len() - 1)]
+    }
+
+    /// 選択中の譜面の試聴を始める位置. 譜面に指定がなければ曲の指定を使う.
+    pub fn preview_start(&self, idx: usize) -> f64 {
+        self.chart(idx).preview_start.unwrap_or(self.preview_start)
+    }
 }
 impl From<SongDataParser> for SongData {
     fn from(data: SongDataParser) -> Self {
         Self {
             name: data.name,
             thumbnail: data.thumbnail,
-            config_file_name: data.config_file_name,
+            charts: data.charts.into_iter().map(ChartData::from).collect(),
             edit_freeze: data.edit_freeze.unwrap_or(true),
+            artist: data.artist,
+            charter: data.charter,
+            genre: data.genre,
+            preview_start: data.preview_start,
         }
     }
 }
@@ -33,3 +153,20 @@ impl From<SongDataParser> for SongData {
 /// 全曲データをロードして選曲ステートに受け渡すためのリソース.
 #[derive(Resource)]
 pub struct AllSongData(pub Vec<SongData>);
+
+/// 選曲画面で選択中の譜面番号. 曲を移動しても保持される.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct SelectedChartIndex(pub usize);
+
+/// 選曲画面で試聴している曲. カードや譜面を移動して音源か試聴位置が変わったら読み込み直す.
+#[derive(Resource)]
+pub struct PreviewMusic {
+    /// 試聴中の音源のファイル名. 音源がない譜面や読めていない譜面ではNone.
+    pub music_filename: Option<String>,
+    /// 試聴を始める位置（秒）
+    pub start: f64,
+    /// 音源がない譜面ではNone
+    pub handle: Option<Handle<AudioSource>>,
+    /// 読み込みが終わって再生を始めたかどうか
+    pub started: bool,
+}
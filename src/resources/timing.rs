@@ -0,0 +1,233 @@
+//! 譜面上の小節と拍の位置から, 判定線に到達する時刻を求める.
+//! ゲームでの読み込み, 譜面の検査, 書き出しで同じ位置を使うように, 時刻の計算はすべてここを通す.
+
+use itertools::Itertools;
+
+use crate::{
+    components::note::{LongNote, NoteInfo},
+    constants::{DISTANCE, HOLD_TICKS_PER_BEAT},
+};
+
+use super::{
+    beat_position::BeatPosition,
+    note::{NoteSpawn, NoteType},
+};
+
+/// ノーツを並べる順のキー. 小節と拍の順で, 同じ位置ではBPM変更・拍子変更を先に並べ,
+/// その位置のノーツが変更後の値で扱われるようにする.
+fn spawn_order_key(note: &NoteSpawn) -> (u32, BeatPosition, bool) {
+    let is_tempo_event = matches!(
+        note.note_type,
+        NoteType::BpmChange { bpm: _ } | NoteType::BeatChange { beat: _ }
+    );
+    (note.bar, note.beat, !is_tempo_event)
+}
+
+/// NoteSpawnの列を小節と拍によりソートする.
+pub fn sort_spawn_notes(notes: &mut [NoteSpawn]) {
+    notes.sort_by_key(spawn_order_key);
+}
+
+/// BPMが変わる位置の列. 通算拍数から判定線に到達する時刻を求めるのに使う.
+struct TempoTimeline {
+    /// BPMが変わる位置の通算拍数と, その位置の時刻, 変更後のBPM. 拍数順に並ぶ.
+    changes: Vec<(BeatPosition, f64, f32)>,
+}
+impl TempoTimeline {
+    /// 0小節目の頭の時刻は譜面のオフセット（秒）とする
+    fn new(initial_bpm: f32, offset: f64) -> Self {
+        Self {
+            changes: vec![(BeatPosition::ZERO, offset, initial_bpm)],
+        }
+    }
+
+    /// 通算拍数の位置からBPMを変える. 位置は拍数順に与える.
+    fn change(&mut self, beat: BeatPosition, bpm: f32) {
+        let time = self.time_at(beat);
+        self.changes.push((beat, time, bpm));
+    }
+
+    fn current_bpm(&self) -> f32 {
+        self.changes.last().unwrap().2
+    }
+
+    /// 通算拍数から時刻を求める.
+    /// 秒への変換はBPMが変わった位置からの拍数で毎回行うので, ノーツ間の誤差が蓄積しない.
+    fn time_at(&self, beat: BeatPosition) -> f64 {
+        let &(change_beat, change_time, bpm) = self
+            .changes
+            .iter()
+            .rev()
+            .find(|(change_beat, _, _)| *change_beat <= beat)
+            .unwrap();
+        change_time + (beat - change_beat).as_f64() * (bpm as f64).recip() * 60.0
+    }
+}
+
+/// 小節と拍の位置に加えて, 曲頭からの通算拍数と時刻（秒）を持ったノーツ.
+pub struct PlacedNote<'a> {
+    pub note: &'a NoteSpawn,
+    /// 0小節目の頭からの通算拍数
+    pub total_beat: BeatPosition,
+    /// 判定線に到達する時刻. 0小節目の頭がオフセットの位置になる.
+    pub time: f64,
+    /// ロングノーツ・ロールノーツの終点の通算拍数と時刻. その他のノーツでは始点と同じ.
+    pub end_beat: BeatPosition,
+    pub end_time: f64,
+    /// ロングノーツ・ロールノーツを押している間の加点の時刻
+    pub tick_times: Vec<f64>,
+    /// その位置でのBPM
+    pub bpm: f32,
+    /// その小節の拍数
+    pub beat_par_bar: u32,
+}
+
+/// ノーツを並べ替え, BPM変更や拍子変更を考慮して通算拍数と時刻を求める.
+/// `offset`は0小節目の頭の時刻（秒）.
+pub fn place_notes(
+    spawn_notes: &[NoteSpawn],
+    initial_bpm: f32,
+    initial_beat: u32,
+    offset: f64,
+) -> Vec<PlacedNote<'_>> {
+    let sorted = spawn_notes
+        .iter()
+        .sorted_by_key(|note| spawn_order_key(note));
+
+    // まず通算拍数を求め, BPMが変わる位置を集める.
+    // 現在の小節の頭の通算拍数. 拍位置は分数なので誤差なく蓄積できる.
+    let mut bar_head = BeatPosition::ZERO;
+    let mut prev_bar = 0u32;
+    // 拍子
+    let mut beat_par_bar = initial_beat;
+    let mut tempo = TempoTimeline::new(initial_bpm, offset);
+    let mut positions = vec![];
+
+    for note in sorted {
+        // 小節をまたぐ場合の拍数はまたぐ前の拍子で計算する.
+        // 間の小節で拍子が変わる場合はその小節に拍子変更ノーツがあるはずなので, ソート順に処理すれば正しく蓄積される.
+        if note.bar != prev_bar {
+            bar_head = bar_head + BeatPosition::from_int((note.bar - prev_bar) * beat_par_bar);
+            prev_bar = note.bar;
+        }
+        let total_beat = bar_head + note.beat;
+        match note.note_type {
+            NoteType::BpmChange { bpm } => tempo.change(total_beat, bpm),
+            // 拍子変更はこの小節の終わりから次の小節へまたぐときに効く
+            NoteType::BeatChange { beat } => beat_par_bar = beat,
+            _ => {}
+        }
+        positions.push((note, total_beat, tempo.current_bpm(), beat_par_bar));
+    }
+
+    // ロングノーツの終点や押している間の加点は始点より後のBPM変更の影響を受けるので,
+    // すべてのBPM変更が分かってから時刻に変換する.
+    positions
+        .into_iter()
+        .map(|(note, total_beat, bpm, beat_par_bar)| {
+            let (end_beat, tick_times) = match note.note_type {
+                NoteType::Long {
+                    key: _,
+                    length,
+                    id: _,
+                }
+                | NoteType::Roll {
+                    key: _,
+                    length,
+                    id: _,
+                } => {
+                    let tick_times = (1..=LongNote::tick_num(length))
+                        .map(|tick| {
+                            tempo.time_at(
+                                total_beat
+                                    + BeatPosition::new(tick as u64, HOLD_TICKS_PER_BEAT as u64),
+                            )
+                        })
+                        .collect_vec();
+                    let length = BeatPosition::from_f32(length).unwrap_or_default();
+                    (total_beat + length, tick_times)
+                }
+                _ => (total_beat, vec![]),
+            };
+            PlacedNote {
+                note,
+                total_beat,
+                time: tempo.time_at(total_beat),
+                end_beat,
+                end_time: tempo.time_at(end_beat),
+                tick_times,
+                bpm,
+                beat_par_bar,
+            }
+        })
+        .collect_vec()
+}
+
+/// barとbeatのみの構造の列からspawn_timeとtarget_timeを持った構造の列に変換する
+pub fn to_notes_info_from_notes_spawn(
+    spawn_notes: Vec<NoteSpawn>,
+    speed: f32,
+    initial_bpm: f32,
+    initial_beat: u32,
+    offset: f64,
+) -> Vec<NoteInfo> {
+    place_notes(&spawn_notes, initial_bpm, initial_beat, offset)
+        .into_iter()
+        .map(|placed| {
+            let spawn_time = placed.time - ((DISTANCE / speed) as f64).abs();
+            NoteInfo {
+                note_type: placed.note.note_type.clone(),
+                target_time: placed.time,
+                spawn_time,
+                end_time: placed.end_time,
+                tick_times: placed.tick_times,
+                bar: placed.note.bar,
+                beat: placed.note.beat,
+                bpm: placed.bpm,
+                sound: placed.note.sound,
+            }
+        })
+        .collect_vec()
+}
+
+#[test]
+fn tempo_change_test() {
+    let spawn = |note_type, bar, beat| NoteSpawn {
+        note_type,
+        bar,
+        beat: BeatPosition::from_int(beat),
+        sound: None,
+    };
+    let notes = to_notes_info_from_notes_spawn(
+        vec![
+            spawn(
+                NoteType::Long {
+                    key: 0,
+                    length: 4.0,
+                    id: 0,
+                },
+                0,
+                2,
+            ),
+            // BPM変更より先に書かれていても, 同じ位置のノーツは変更後のBPMで扱う
+            spawn(NoteType::Normal { key: 1 }, 1, 0),
+            spawn(NoteType::BpmChange { bpm: 60.0 }, 1, 0),
+        ],
+        1.0,
+        120.0,
+        4,
+        0.0,
+    );
+    let long = &notes[0];
+    assert_eq!(long.target_time, 1.0);
+    // 2拍分は120BPM, 残りの2拍分は60BPMで進む
+    assert_eq!(long.end_time, 4.0);
+    assert_eq!(long.tick_times, vec![1.25, 1.5, 1.75, 2.0, 2.5, 3.0, 3.5]);
+    // 押している間の一拍はBPM変更の前後でそれぞれのBPMの長さになる
+    assert_eq!(long.time_after_beats(1.0, 1.0), 1.5);
+    assert_eq!(long.time_after_beats(1.75, 1.0), 2.5);
+    assert_eq!(long.time_after_beats(3.0, 1.0), 4.0);
+    assert!(matches!(notes[1].note_type, NoteType::BpmChange { bpm: _ }));
+    assert_eq!(notes[2].target_time, 2.0);
+    assert_eq!(notes[2].bpm, 60.0);
+}
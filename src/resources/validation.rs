@@ -0,0 +1,326 @@
+//! 譜面データの検査を行う.
+//! yamlは手書きされるため, 同じ位置へのノーツの重複やロングノーツのidの重複などが起きやすい.
+//! 読み込み時に検査して, 壊れた譜面をそのまま流さないようにする.
+
+use std::{collections::HashMap, fmt};
+
+use itertools::Itertools;
+
+use crate::components::note::KeyLane;
+
+use super::{beat_position::BeatPosition, note::NoteType, song::SongConfig, timing::place_notes};
+
+/// 譜面の問題点の種類
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChartIssueKind {
+    /// 対応していない鍵盤数が指定されている
+    UnsupportedLaneNum { lane_num: u8 },
+    /// 鍵盤番号がレーンの範囲外
+    KeyOutOfRange { key: i32, lane_num: u8 },
+    /// 拍位置が小節の範囲外
+    BeatOutOfBar { beat_par_bar: u32 },
+    /// 拍子変更が小節の頭にない
+    BeatChangeNotAtBarHead,
+    /// 同じレーンの同じ位置に複数のノーツがある
+    OverlappingNotes { key: i32 },
+    /// ロングノーツの押している間に同じレーンの別のノーツがある
+    LongNoteOverlap {
+        key: i32,
+        bar: u32,
+        beat: BeatPosition,
+    },
+    /// ロングノーツのidが重複している
+    DuplicateLongNoteId {
+        id: u32,
+        bar: u32,
+        beat: BeatPosition,
+    },
+    /// 曲の尺を超えた位置にノーツがある
+    PastSongLength { time: f64, length: f64 },
+    /// キー音の番号が`keysounds`の範囲外
+    KeySoundOutOfRange { sound: u32, keysound_num: usize },
+}
+
+impl ChartIssueKind {
+    /// 譜面として読み込めない問題かどうか.
+    /// 同じ位置に重なったノーツは後のノーツが取れなくなるのでエラーとする.
+    /// ロングノーツの押している間に置かれたノーツは遊べなくはないので警告に留める.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, ChartIssueKind::LongNoteOverlap { .. })
+    }
+}
+
+/// 譜面の問題点. どの位置のノーツに問題があるかを持つ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartIssue {
+    pub bar: u32,
+    pub beat: BeatPosition,
+    pub kind: ChartIssueKind,
+}
+impl ChartIssue {
+    pub fn is_error(&self) -> bool {
+        self.kind.is_error()
+    }
+}
+impl fmt::Display for ChartIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bar {}, beat {}: ", self.bar, self.beat)?;
+        match &self.kind {
+            ChartIssueKind::UnsupportedLaneNum { lane_num } => write!(
+                f,
+                "lane_num {} is not supported (use {}..={})",
+                lane_num,
+                KeyLane::MIN_KEY_NUM,
+                KeyLane::MAX_KEY_NUM
+            ),
+            ChartIssueKind::KeyOutOfRange { key, lane_num } => write!(
+                f,
+                "key {} is out of range (lane_num is {}, so key must be 0..{})",
+                key, lane_num, lane_num
+            ),
+            ChartIssueKind::BeatOutOfBar { beat_par_bar } => write!(
+                f,
+                "beat must be in 0..{} because this bar has {} beats",
+                beat_par_bar, beat_par_bar
+            ),
+            ChartIssueKind::BeatChangeNotAtBarHead => {
+                write!(f, "BeatChange must be placed at beat 0")
+            }
+            ChartIssueKind::OverlappingNotes { key } => {
+                write!(
+                    f,
+                    "multiple notes are placed at the same position on key {}",
+                    key
+                )
+            }
+            ChartIssueKind::LongNoteOverlap { key, bar, beat } => write!(
+                f,
+                "this note on key {} overlaps the long note starting at bar {}, beat {}",
+                key, bar, beat
+            ),
+            ChartIssueKind::DuplicateLongNoteId { id, bar, beat } => write!(
+                f,
+                "long note id {} is already used at bar {}, beat {}",
+                id, bar, beat
+            ),
+            ChartIssueKind::PastSongLength { time, length } => write!(
+                f,
+                "note at {:.3} sec is past the song length {:.3} sec",
+                time, length
+            ),
+            ChartIssueKind::KeySoundOutOfRange {
+                sound,
+                keysound_num,
+            } => write!(
+                f,
+                "keysound {} is not defined ({} keysounds are listed)",
+                sound, keysound_num
+            ),
+        }
+    }
+}
+
+/// 鍵盤番号を持つノーツならその番号を返す
+fn note_key(note_type: &NoteType) -> Option<i32> {
+    match note_type {
+        NoteType::Normal { key }
+        | NoteType::AdLib { key }
+        | NoteType::Mine { key }
+        | NoteType::Long {
+            key,
+            length: _,
+            id: _,
+        }
+        | NoteType::Roll {
+            key,
+            length: _,
+            id: _,
+        } => Some(*key),
+        _ => None,
+    }
+}
+
+/// 譜面を検査し, 見つかった問題点をすべて返す. 問題がなければ空になる.
+pub fn validate_song_config(config: &SongConfig) -> Vec<ChartIssue> {
+    let mut issues = vec![];
+    let lane_num = config.lane_num;
+    if !(KeyLane::MIN_KEY_NUM..=KeyLane::MAX_KEY_NUM).contains(&lane_num) {
+        issues.push(ChartIssue {
+            bar: 0,
+            beat: BeatPosition::ZERO,
+            kind: ChartIssueKind::UnsupportedLaneNum { lane_num },
+        });
+    }
+
+    let placed = place_notes(
+        &config.notes,
+        config.initial_bpm,
+        config.initial_beat,
+        config.offset / 1000.0,
+    );
+    let mut long_note_ids = HashMap::<u32, (u32, BeatPosition)>::new();
+
+    for p in placed.iter() {
+        let note = p.note;
+        let mut push = |kind| {
+            issues.push(ChartIssue {
+                bar: note.bar,
+                beat: note.beat,
+                kind,
+            })
+        };
+
+        if note.beat >= BeatPosition::from_int(p.beat_par_bar) {
+            push(ChartIssueKind::BeatOutOfBar {
+                beat_par_bar: p.beat_par_bar,
+            });
+        }
+        if matches!(note.note_type, NoteType::BeatChange { beat: _ })
+            && note.beat != BeatPosition::ZERO
+        {
+            push(ChartIssueKind::BeatChangeNotAtBarHead);
+        }
+        if let Some(key) = note_key(&note.note_type) {
+            if key < 0 || key >= lane_num as i32 {
+                push(ChartIssueKind::KeyOutOfRange { key, lane_num });
+            }
+        }
+        let sound = match note.note_type {
+            NoteType::KeySound { sound } => Some(sound),
+            _ => note.sound,
+        };
+        if let Some(sound) = sound {
+            if sound as usize >= config.keysounds.len() {
+                push(ChartIssueKind::KeySoundOutOfRange {
+                    sound,
+                    keysound_num: config.keysounds.len(),
+                });
+            }
+        }
+        // ロングノーツとロールは終端の時刻も尺に収まっている必要がある
+        if p.end_time > config.length {
+            push(ChartIssueKind::PastSongLength {
+                time: p.end_time,
+                length: config.length,
+            });
+        }
+        if let NoteType::Long { id, .. } | NoteType::Roll { id, .. } = note.note_type {
+            if let Some(&(bar, beat)) = long_note_ids.get(&id) {
+                push(ChartIssueKind::DuplicateLongNoteId { id, bar, beat });
+            } else {
+                long_note_ids.insert(id, (note.bar, note.beat));
+            }
+        }
+    }
+
+    // レーンごとに重なりを調べる
+    let lanes = placed
+        .iter()
+        .filter_map(|p| note_key(&p.note.note_type).map(|key| (key, p)))
+        .into_group_map();
+    for (key, lane_notes) in lanes.into_iter().sorted_by_key(|(key, _)| *key) {
+        for (i, p) in lane_notes.iter().enumerate() {
+            if let Some(prev) = i.checked_sub(1).map(|j| lane_notes[j]) {
+                if p.total_beat == prev.total_beat {
+                    issues.push(ChartIssue {
+                        bar: p.note.bar,
+                        beat: p.note.beat,
+                        kind: ChartIssueKind::OverlappingNotes { key },
+                    });
+                }
+            }
+            if let NoteType::Long { .. } | NoteType::Roll { .. } = p.note.note_type {
+                for later in lane_notes[i + 1..]
+                    .iter()
+                    .filter(|later| later.total_beat > p.total_beat)
+                    .take_while(|later| later.total_beat < p.end_beat)
+                {
+                    issues.push(ChartIssue {
+                        bar: later.note.bar,
+                        beat: later.note.beat,
+                        kind: ChartIssueKind::LongNoteOverlap {
+                            key,
+                            bar: p.note.bar,
+                            beat: p.note.beat,
+                        },
+                    });
+                }
+            }
+        }
+    }
+
+    issues
+}
+
+#[test]
+fn validate_test() {
+    use super::note::NoteSpawn;
+
+    let spawn = |note_type, bar, beat| NoteSpawn {
+        note_type,
+        bar,
+        beat: BeatPosition::from_f64(beat).unwrap(),
+        sound: None,
+    };
+    let config = SongConfig {
+        name: "test".to_string(),
+        filename: "test.wav".to_string(),
+        length: 10.0,
+        initial_beat: 4,
+        initial_bpm: 120.0,
+        offset: 0.0,
+        lane_num: 4,
+        notes: vec![
+            spawn(NoteType::Normal { key: 0 }, 0, 0.0),
+            spawn(NoteType::Normal { key: 0 }, 0, 0.0),
+            spawn(NoteType::Normal { key: 4 }, 0, 1.0),
+            spawn(NoteType::Normal { key: 1 }, 0, 4.0),
+            spawn(
+                NoteType::Long {
+                    key: 2,
+                    length: 2.0,
+                    id: 0,
+                },
+                1,
+                0.0,
+            ),
+            spawn(NoteType::Normal { key: 2 }, 1, 1.0),
+            spawn(
+                NoteType::Long {
+                    key: 3,
+                    length: 1.0,
+                    id: 0,
+                },
+                1,
+                0.0,
+            ),
+            // 120BPMの4拍子なので10秒は5小節目の頭
+            spawn(NoteType::Normal { key: 1 }, 5, 1.0),
+        ],
+        keysounds: vec![],
+    };
+    let kinds = validate_song_config(&config)
+        .into_iter()
+        .map(|issue| issue.kind)
+        .collect_vec();
+    assert!(kinds.contains(&ChartIssueKind::OverlappingNotes { key: 0 }));
+    assert!(kinds.contains(&ChartIssueKind::KeyOutOfRange {
+        key: 4,
+        lane_num: 4
+    }));
+    assert!(kinds.contains(&ChartIssueKind::BeatOutOfBar { beat_par_bar: 4 }));
+    assert!(kinds.contains(&ChartIssueKind::LongNoteOverlap {
+        key: 2,
+        bar: 1,
+        beat: BeatPosition::ZERO
+    }));
+    assert!(kinds.contains(&ChartIssueKind::DuplicateLongNoteId {
+        id: 0,
+        bar: 1,
+        beat: BeatPosition::ZERO
+    }));
+    assert!(kinds
+        .iter()
+        .any(|kind| matches!(kind, ChartIssueKind::PastSongLength { .. })));
+    assert_eq!(kinds.len(), 6);
+}
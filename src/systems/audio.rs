@@ -3,17 +3,26 @@ use bevy_kira_audio::prelude::*;
 
 use crate::{
     add_enter_system, add_update_system,
+    components::note::NoteInfo,
     constants::MUSIC_PLAY_PRECOUNT,
-    events::PanicAudio,
-    resources::{handles::GameAssetsHandles, song::SongStartTime},
+    events::{NoteEvalEvent, PanicAudio},
+    resources::{
+        config::{GlobalOffset, UserConfigParser, USER_CONFIG_PATH},
+        handles::GameAssetsHandles,
+        note::NoteType,
+        score::{CatchEval, JudgePoint},
+        song::SongStartTime,
+    },
     AppState,
 };
 
 use super::system_labels::TimerSystemLabel;
 
-fn setup_start_song(mut commands: Commands, time: Res<Time>) {
+/// 全体オフセットの分だけ譜面側の基準時刻を遅らせる.
+/// 曲の再生はオフセットを含まない時刻に行うので, 結果として譜面と曲の相対位置がずれる.
+fn setup_start_song(mut commands: Commands, time: Res<Time>, global_offset: Res<GlobalOffset>) {
     commands.insert_resource(SongStartTime(
-        time.elapsed_seconds_f64() + MUSIC_PLAY_PRECOUNT,
+        time.elapsed_seconds_f64() + MUSIC_PLAY_PRECOUNT + global_offset.as_secs(),
     ));
 }
 
@@ -22,19 +31,26 @@ fn start_song(
     start_time: Res<SongStartTime>,
     time: Res<Time>,
     handles: Res<GameAssetsHandles>,
+    global_offset: Res<GlobalOffset>,
 ) {
-    // 曲開始時刻から現在時刻までの差
-    let time_after_start = start_time.time_after_start(&time);
+    // 曲を再生すべき時刻から現在時刻までの差
+    let time_after_start = start_time.time_after_start(&time) + global_offset.as_secs();
     let time_last = time_after_start - time.delta_seconds_f64();
     if (time_last..time_after_start).contains(&0.0) {
         info!("music start");
-        audio.play(handles.music.clone());
+        if let Some(music) = handles.music.as_ref() {
+            audio.play(music.clone());
+        }
     }
 }
 
-fn setup_editor_start_song(mut commands: Commands, time: Res<Time>) {
+fn setup_editor_start_song(
+    mut commands: Commands,
+    time: Res<Time>,
+    global_offset: Res<GlobalOffset>,
+) {
     commands.insert_resource(SongStartTime(
-        time.elapsed_seconds_f64() + MUSIC_PLAY_PRECOUNT,
+        time.elapsed_seconds_f64() + MUSIC_PLAY_PRECOUNT + global_offset.as_secs(),
     ));
 }
 fn editor_start_song(
@@ -42,12 +58,62 @@ fn editor_start_song(
     start_time: Res<SongStartTime>,
     time: Res<Time>,
     handles: Res<GameAssetsHandles>,
+    global_offset: Res<GlobalOffset>,
 ) {
-    let time_after_start = start_time.time_after_start(&time);
+    let time_after_start = start_time.time_after_start(&time) + global_offset.as_secs();
     let time_last = time_after_start - time.delta_seconds_f64();
     if (time_last..time_after_start).contains(&0.0) {
         info!("editor music start");
-        audio.play(handles.music.clone());
+        if let Some(music) = handles.music.as_ref() {
+            audio.play(music.clone());
+        }
+    }
+}
+
+/// キー音イベントが判定線に到達したら鳴らす.
+/// 曲の再生と同じく全体オフセットを含まない時刻で判定する.
+fn play_keysounds(
+    mut commands: Commands,
+    audio: Res<Audio>,
+    note_q: Query<(&NoteInfo, Entity)>,
+    start_time: Res<SongStartTime>,
+    time: Res<Time>,
+    handles: Res<GameAssetsHandles>,
+    global_offset: Res<GlobalOffset>,
+) {
+    let time_after_start = start_time.time_after_start(&time) + global_offset.as_secs();
+    for (note, ent) in note_q.iter() {
+        let NoteType::KeySound { sound } = note.note_type else {
+            continue;
+        };
+        if time_after_start < note.target_time {
+            continue;
+        }
+        if let Some(handle) = handles.keysounds.get(sound as usize) {
+            audio.play(handle.clone());
+        }
+        commands.entity(ent).despawn();
+    }
+}
+
+/// ノーツを取得したらそのノーツのキー音を鳴らす.
+/// ロングノーツでは始点を叩いたときに鳴らし, 取り逃したノーツでは鳴らさない.
+fn play_hit_keysounds(
+    audio: Res<Audio>,
+    mut ev_reader: EventReader<NoteEvalEvent>,
+    handles: Res<GameAssetsHandles>,
+) {
+    for ev in ev_reader.iter() {
+        if ev.point != JudgePoint::Head || matches!(ev.eval, CatchEval::Miss | CatchEval::Mine) {
+            continue;
+        }
+        if let Some(handle) = ev
+            .note
+            .sound
+            .and_then(|s| handles.keysounds.get(s as usize))
+        {
+            audio.play(handle.clone());
+        }
     }
 }
 
@@ -60,8 +126,14 @@ fn panic_audio(audio: Res<Audio>, ev_reader: EventReader<PanicAudio>) {
 pub struct GameAudioPlugin;
 impl Plugin for GameAudioPlugin {
     fn build(&self, app: &mut App) {
+        // 全体オフセットはゲームを通して保持し, 起動時に設定ファイルから読み込む
+        app.insert_resource(GlobalOffset::from(&UserConfigParser::load(
+            USER_CONFIG_PATH,
+        )));
         add_enter_system!(app, Game, setup_start_song);
         add_update_system!(app, Game, start_song, [], TimerSystemLabel::StartAudio);
+        add_update_system!(app, Game, play_keysounds);
+        add_update_system!(app, Game, play_hit_keysounds);
         add_enter_system!(app, Editor, setup_editor_start_song);
         add_update_system!(
             app,
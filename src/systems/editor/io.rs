@@ -8,14 +8,16 @@ use crate::{
     components::{note::NoteInfo, ui::EditorStateObject},
     events::PanicAudio,
     resources::{
+        asset_loader::{ChartAsset, ChartHandle},
         editor::{EditorNotesQueue, QuittingEditor},
         game_state::NextAppState,
         handles::GameAssetsHandles,
         note::{NoteSpawn, NoteType},
         song::{SongConfig, SongConfigParser},
-        song_list::SongData,
+        song_list::ChartData,
+        timing::sort_spawn_notes,
     },
-    systems::load::{load_song_config, sort_spawn_notes},
+    systems::load::song_config_from_asset,
     AppState,
 };
 
@@ -36,6 +38,25 @@ fn merge_song_config(mut song_config: SongConfig, new_notes: Vec<NoteSpawn>) ->
     song_config
 }
 
+/// ノーツ列を並べ替え, 同じ位置にある同じノーツを一つにする.
+/// グリッドに寄せた結果同じ位置に重なったノーツや, 既存のノーツと重なったノーツが対象になる.
+fn merge_notes(mut notes: Vec<NoteSpawn>) -> Vec<NoteSpawn> {
+    sort_spawn_notes(&mut notes);
+    let mut merged: Vec<NoteSpawn> = vec![];
+    for note in notes {
+        // 同じ位置のノーツは並べ替えで隣り合うが, 別のレーンのノーツが間に入ることがあるので位置ごとにすべて比べる
+        let is_duplicated = merged
+            .iter()
+            .rev()
+            .take_while(|prev| prev.bar == note.bar && prev.beat == note.beat)
+            .any(|prev| prev.note_type == note.note_type);
+        if !is_duplicated {
+            merged.push(note);
+        }
+    }
+    merged
+}
+
 /// エディットモードをやめて保存するか聞く
 fn quit_editting(
     mut commands: Commands,
@@ -87,13 +108,16 @@ fn quit_editting(
 }
 
 /// 保存するまたはしないを決めて戻る処理. 保存の有無でシステムを分けると同時に実行されると危ない
+#[allow(clippy::too_many_arguments)]
 fn back_to_home(
     mut commands: Commands,
     quitting: Option<Res<QuittingEditor>>,
     mut key_input: ResMut<Input<KeyCode>>,
     mut state: ResMut<State<AppState>>,
     queue: Res<EditorNotesQueue>,
-    song_data: Res<SongData>,
+    chart_data: Res<ChartData>,
+    chart_handle: Res<ChartHandle>,
+    charts: Res<Assets<ChartAsset>>,
 ) {
     if quitting.is_some()
         && key_input.pressed(KeyCode::E)
@@ -101,24 +125,34 @@ fn back_to_home(
     {
         // 保存する場合は追加の操作
         if key_input.just_pressed(KeyCode::S) {
-            // 新しく譜面データを読み出し
-            let song_config = load_song_config(&song_data.config_file_name);
+            // 譜面データを取得. 編集中にファイルが変更されていれば再読み込みされたものになる.
+            // 編集中に譜面ファイルが壊れていた場合は保存せずエラー画面へ移行する
+            let asset = charts.get(&chart_handle).unwrap();
+            let song_config = match song_config_from_asset(&chart_data.config_file_name, asset) {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("{}", e);
+                    key_input.clear();
+                    commands.insert_resource(e.with_back_to(AppState::HomeMenu));
+                    state.set(AppState::LoadError).unwrap();
+                    return;
+                }
+            };
             let mut old_notes = song_config.notes.clone();
             let new_notes = queue
                 .iter()
                 .map(|n| NoteSpawn {
-                    // TODO: ここでグリッドスナップ
+                    // 拍位置は入力時にグリッドに寄せてある
                     note_type: NoteType::Normal { key: n.key },
                     bar: n.bar,
                     beat: n.beat,
+                    sound: None,
                 })
                 .collect_vec();
             old_notes.extend(new_notes);
-            let mut merged_notes = old_notes;
-            sort_spawn_notes(&mut merged_notes);
-            let merged_config = merge_song_config(song_config, merged_notes);
+            let merged_config = merge_song_config(song_config, merge_notes(old_notes));
             // NOTE: 現状では（安定化するまで）バイナリの実行ディレクトリに吐き出される仕様となっている.
-            output_chart(&song_data.config_file_name, merged_config).unwrap();
+            output_chart(&chart_data.config_file_name, merged_config).unwrap();
         }
         key_input.clear();
         commands.insert_resource(NextAppState(AppState::HomeMenu));
@@ -143,3 +177,34 @@ impl Plugin for EditorInOutPlugin {
         add_exit_system!(app, Editor, exit_editor_state);
     }
 }
+
+#[test]
+fn merge_notes_test() {
+    use crate::resources::beat_position::BeatPosition;
+
+    let spawn = |key, beat| NoteSpawn {
+        note_type: NoteType::Normal { key },
+        bar: 0,
+        beat,
+        sound: None,
+    };
+    let half = BeatPosition::new(1, 2);
+    // 同じ位置に別のレーンのノーツが挟まっていても重複を取り除く
+    let merged = merge_notes(vec![
+        spawn(0, half),
+        spawn(1, half),
+        spawn(0, half),
+        spawn(0, BeatPosition::ZERO),
+    ]);
+    assert_eq!(
+        merged
+            .iter()
+            .map(|note| (note.note_type.clone(), note.beat))
+            .collect_vec(),
+        vec![
+            (NoteType::Normal { key: 0 }, BeatPosition::ZERO),
+            (NoteType::Normal { key: 0 }, half),
+            (NoteType::Normal { key: 1 }, half),
+        ]
+    );
+}
@@ -2,14 +2,17 @@ use bevy::prelude::*;
 use bevy::time::FixedTimestep;
 
 use crate::components::note::{KeyLane, NoteInfo};
-use crate::constants::{BASIC_NOTE_SPEED, FRAMERATE, MISS_THR, SCREEN_HEIGHT, TARGET_Y};
+use crate::constants::{
+    BASIC_NOTE_SPEED, EDITOR_GRID_DIVISION, FRAMERATE, SCREEN_HEIGHT, TARGET_Y,
+};
 use crate::events::EditNoteEvent;
 use crate::resources::editor::{EditNote, EditorBar, EditorBeat, EditorNotesQueue};
 use crate::resources::note::NoteType;
 use crate::resources::{
-    config::{Beat, Bpm, NoteSpeed},
+    beat_position::BeatPosition,
+    config::{Beat, Bpm, LaneNum, NoteSpeed},
     handles::GameAssetsHandles,
-    song::{SongNotes, SongStartTime},
+    song::{SongConfigResource, SongNotes, SongStartTime},
 };
 use crate::{add_enter_system, add_update_system, AppState};
 
@@ -30,7 +33,7 @@ fn spawn_notes(
     notes: Option<ResMut<SongNotes>>,
     start_time: Option<Res<SongStartTime>>,
     speed: Option<Res<NoteSpeed>>,
-    bpm: Option<Res<Bpm>>,
+    lane_num: Option<Res<LaneNum>>,
     time: Option<Res<Time>>,
     mut color_material: ResMut<Assets<ColorMaterial>>,
     state: Res<State<AppState>>,
@@ -44,7 +47,7 @@ fn spawn_notes(
     let start_time = start_time.unwrap();
     let time = time.unwrap();
     let speed = speed.unwrap();
-    let bpm = bpm.unwrap();
+    let lane_num = lane_num.unwrap();
 
     let time_after_start = start_time.time_after_start(&time);
 
@@ -61,7 +64,8 @@ fn spawn_notes(
             &mut color_material,
             &note.note_type,
             **speed,
-            **bpm,
+            note.end_time - note.target_time,
+            **lane_num,
             true,
         );
         let note_bundle = (note, note_mesh);
@@ -81,7 +85,8 @@ fn move_notes(
     }
 }
 
-/// 小節と拍を更新する
+/// 小節と拍を更新する.
+/// 小節の繰り上げは現在の`Beat`で行い, 拍子変更はその後`execute_notes`で反映される.
 fn update_bar_and_beat(
     mut bar: ResMut<EditorBar>,
     mut beat: ResMut<EditorBeat>,
@@ -89,9 +94,11 @@ fn update_bar_and_beat(
     time: Res<Time>,
     bpm: Res<Bpm>,
     beat_par_bar: Res<Beat>,
+    song_config: Res<SongConfigResource>,
 ) {
     let time_after_start = start_time.time_after_start(&time);
-    if time_after_start > 0.0 {
+    // 0小節目の頭は譜面のオフセットの位置
+    if time_after_start > song_config.offset {
         let time_diff = time.raw_delta_seconds_f64();
         // 拍の差分
         let beat_diff = time_diff / 60.0 * **bpm as f64;
@@ -116,17 +123,26 @@ fn input_notes(
     time: Res<Time>,
     current_bar: Res<EditorBar>,
     current_beat: Res<EditorBeat>,
+    beat_par_bar: Res<Beat>,
     mut ev_writer: EventWriter<EditNoteEvent>,
+    song_config: Res<SongConfigResource>,
 ) {
-    if start_time.time_after_start(&time) < 0.0 {
+    if start_time.time_after_start(&time) < song_config.offset {
         return;
     }
+    // 押した位置をグリッドに寄せる. 小節の終わりに寄った場合は次の小節の頭とする.
+    let beat = BeatPosition::snap(**current_beat, EDITOR_GRID_DIVISION);
+    let (bar, beat) = if beat >= BeatPosition::from_int(**beat_par_bar) {
+        (**current_bar + 1, BeatPosition::ZERO)
+    } else {
+        (**current_bar, beat)
+    };
     for lane in lane_q.iter_mut() {
         if lane.key_just_pressed(&key_input) {
             let note = EditNote {
-                key: lane.0,
-                bar: **current_bar,
-                beat: **current_beat,
+                key: lane.num,
+                bar,
+                beat,
             };
             ev_writer.send(note.clone().into());
             queue.push_back(note);
@@ -139,11 +155,13 @@ fn spawn_edit_note(
     mut commands: Commands,
     mut ev_reader: EventReader<EditNoteEvent>,
     game_assets: Res<GameAssetsHandles>,
+    bpm: Res<Bpm>,
+    lane_num: Res<LaneNum>,
 ) {
     for ev in ev_reader.iter() {
         let key = ev.key;
         let transform = Transform {
-            translation: Vec3::new(KeyLane::x_coord_from_num(key), TARGET_Y, 1.0),
+            translation: Vec3::new(KeyLane::x_coord_from_num(key, **lane_num), TARGET_Y, 1.0),
             ..Default::default()
         };
         let mesh = ColorMesh2dBundle {
@@ -155,9 +173,13 @@ fn spawn_edit_note(
         let note_info = NoteInfo {
             note_type: NoteType::Normal { key },
             bar: 0,
-            beat: 0.0,
+            beat: BeatPosition::ZERO,
             spawn_time: 0.0,
             target_time: 0.0,
+            end_time: 0.0,
+            tick_times: vec![],
+            bpm: **bpm,
+            sound: None,
         };
         commands.spawn((note_info, mesh));
     }
@@ -165,31 +187,28 @@ fn spawn_edit_note(
 
 /// BPM変更等, ノーツの種類によっては処理するためのもの
 fn execute_notes(
+    mut commands: Commands,
     note_q: Query<(&NoteInfo, Entity)>,
-    mut lane_q: Query<&KeyLane>,
     start_time: Res<SongStartTime>,
-    key_input: Res<Input<KeyCode>>,
     time: Res<Time>,
-    _bpm: Res<Bpm>,
-    _beat: Res<Beat>,
+    current_bar: Res<EditorBar>,
+    mut bpm: ResMut<Bpm>,
+    mut beat: ResMut<Beat>,
 ) {
     let time_after_start = start_time.time_after_start(&time);
-    for lane in lane_q.iter_mut() {
-        for (note, _) in note_q.iter() {
-            let note_target_time = note.target_time;
-            // 現在時刻が許容範囲・鍵盤番号が一致・キーがちょうど押された・まだ消去されていないノートを取得処理
-            if (note_target_time - MISS_THR..=note_target_time + MISS_THR)
-                .contains(&time_after_start)
-                && lane.key_just_pressed(&key_input)
-            {
-                // execute something
-                // match note.note_type {
-                //     NoteType::Normal { key: _ } => false,
-                //     NoteType::BarLine => false,
-                //     NoteType::AdLib { key: _ } => false,
-                // }
+    for (note, ent) in note_q.iter() {
+        match note.note_type {
+            NoteType::BpmChange { bpm: new_bpm } if time_after_start >= note.target_time => {
+                **bpm = new_bpm;
+            }
+            // 拍子はエディタの小節番号が切り替わった時点で反映する.
+            // 時刻で判定すると小節の繰り上げより先に拍子が変わってしまうことがある.
+            NoteType::BeatChange { beat: new_beat } if **current_bar >= note.bar => {
+                **beat = new_beat;
             }
+            _ => continue,
         }
+        commands.entity(ent).despawn();
     }
 }
 
@@ -234,7 +253,12 @@ impl Plugin for EditorNotePlugin {
             spawn_edit_note,
             [after: EditorSystemLabel::UpdateBarAndBeat]
         );
-        add_update_system!(app, Editor, execute_notes);
+        add_update_system!(
+            app,
+            Editor,
+            execute_notes,
+            [after: EditorSystemLabel::UpdateBarAndBeat]
+        );
         add_update_system!(app, Editor, drop_notes);
     }
 }
@@ -9,7 +9,11 @@ use crate::{
         ui::{ChartInfoNode, EditorStateObject, LaneLine, TargetLine},
     },
     constants::{LANE_WIDTH, TARGET_Y},
-    resources::{config::GameDifficulty, handles::GameAssetsHandles, song::SongConfigResource},
+    resources::{
+        config::{GameDifficulty, LaneNum},
+        handles::GameAssetsHandles,
+        song::SongConfigResource,
+    },
     spawn_text_node,
     systems::system_labels::TimerSystemLabel,
     AppState,
@@ -20,6 +24,7 @@ fn setup_ui(
     song_config: Res<SongConfigResource>,
     diff: Res<GameDifficulty>,
     handles: Res<GameAssetsHandles>,
+    lane_num: Res<LaneNum>,
 ) {
     let font = handles.main_font.clone();
     // 曲名・難易度表示ノード
@@ -51,8 +56,8 @@ fn setup_ui(
         .insert(EditorStateObject);
 
     // 鍵盤線
-    for i in 0..5 {
-        let x = KeyLane::x_coord_from_num(i);
+    for i in 0..=**lane_num as i32 {
+        let x = KeyLane::x_coord_from_num(i, **lane_num);
         let transform = Transform {
             translation: Vec3::new(x - LANE_WIDTH / 2.0, TARGET_Y + 250.0, 2.0),
             ..Default::default()
@@ -96,9 +101,9 @@ fn beat_and_bar_text(
     }
 }
 
-fn setup_lane(mut commands: Commands, handles: Res<GameAssetsHandles>) {
-    for i in 0..4 {
-        let x = KeyLane::x_coord_from_num(i);
+fn setup_lane(mut commands: Commands, handles: Res<GameAssetsHandles>, lane_num: Res<LaneNum>) {
+    for i in 0..**lane_num as i32 {
+        let x = KeyLane::x_coord_from_num(i, **lane_num);
         let transform = Transform {
             translation: Vec3::new(x, TARGET_Y + 250.0, 0.1),
             ..Default::default()
@@ -110,7 +115,7 @@ fn setup_lane(mut commands: Commands, handles: Res<GameAssetsHandles>) {
                 transform,
                 ..Default::default()
             })
-            .insert(KeyLane(i))
+            .insert(KeyLane::new(i, **lane_num))
             .insert(EditorStateObject)
             .insert(FrameCounter::new_default(60));
     }
@@ -1,5 +1,4 @@
-use std::io::prelude::*;
-use std::{collections::VecDeque, fs::File};
+use std::collections::VecDeque;
 
 use bevy::{asset::LoadState, prelude::*};
 use itertools::Itertools;
@@ -7,108 +6,92 @@ use itertools::Itertools;
 use crate::resources::handles::HomeMenuAssetHandles;
 use crate::{add_enter_system, add_exit_system, add_update_system};
 use crate::{
-    components::{load::NowLoadingText, note::NoteInfo},
-    constants::{BASIC_NOTE_SPEED, DISTANCE},
+    components::load::NowLoadingText,
+    constants::BASIC_NOTE_SPEED,
+    events::PanicAudio,
     resources::{
-        config::{Beat, Bpm, GameDifficulty, NoteSpeed},
-        game_state::NextAppState,
+        asset_loader::{
+            ChartAsset, ChartAssetLoader, ChartHandle, JudgeConfigAsset, JudgeConfigAssetLoader,
+            JudgeConfigHandle, SongListAsset, SongListAssetLoader, SongListHandle, SONG_LIST_PATH,
+        },
+        beat_position::BeatPosition,
+        config::{Beat, Bpm, GameDifficulty, LaneNum, NoteSpeed},
+        game_state::{NextAppState, ResultDisplayed, WaitingSceneData},
         handles::{AssetHandles, AssetsLoading, GameAssetsHandles, SongSelectAssetHandles},
+        judge::{JudgeProfile, JUDGE_CONFIG_PATH},
+        load_error::{LoadError, LoadErrorKind},
         note::{NoteSpawn, NoteType},
-        score::ScoreResource,
-        song::{SongConfig, SongConfigParser, SongConfigResource, SongNotes},
-        song_list::{AllSongData, SongData, SongDataParser},
+        score::{max_raw_score, ScoreResource},
+        song::{SongConfig, SongConfigResource, SongNotes},
+        song_list::{AllSongData, ChartData, SongData},
+        timing::to_notes_info_from_notes_spawn,
+        validation::{validate_song_config, ChartIssue},
     },
     AppState,
 };
 
-/// 曲一覧情報をファイルから取得する.
-fn load_all_config_file_data() -> Vec<SongDataParser> {
-    let mut file = File::open("assets/songs/all_song_data.yaml").expect("Couldn't open file");
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .expect("Couldn't read file into String");
+use super::system_labels::LoadSystemLabel;
 
-    let parsed: Vec<SongDataParser> =
-        serde_yaml::from_str(&contents).expect("Couldn't parse into data array");
-    parsed
-}
-
-/// 指定された曲情報ファイルの内容を返す
-pub(super) fn load_song_config(filename: &str) -> SongConfig {
-    let mut file = File::open(format!("assets/songs/{}", filename)).expect("Couldn't open file");
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .expect("Couldn't read file into String");
-
-    let parsed: SongConfigParser =
-        serde_yaml::from_str(&contents).expect("Couldn't parse into SongConfigParser");
-
-    SongConfig::from(parsed)
+/// 曲一覧アセットの内容を検査して曲データの列にする.
+fn song_list_from_asset(asset: &SongListAsset) -> Result<Vec<SongData>, LoadError> {
+    let path = format!("assets/{}", SONG_LIST_PATH);
+    let parsed = match &asset.0 {
+        Ok(parsed) => parsed,
+        Err(e) => return Err(LoadError::new(path, LoadErrorKind::Parse(e.clone()))),
+    };
+    // 譜面が一つもない曲は選択できないのでエラーとする
+    if let Some(data) = parsed.iter().find(|data| data.charts.is_empty()) {
+        return Err(LoadError::new(
+            path,
+            LoadErrorKind::InvalidSongList(format!("song '{}' has no charts", data.name)),
+        ));
+    }
+    Ok(parsed.iter().cloned().map(SongData::from).collect_vec())
 }
 
-/// NoteSpawnの列を小節と拍によりソートする.
-pub fn sort_spawn_notes(notes: &mut [NoteSpawn]) {
-    notes.sort_by(|a, b| match a.bar.cmp(&b.bar) {
-        std::cmp::Ordering::Equal => a.beat.partial_cmp(&b.beat).unwrap(),
-        std::cmp::Ordering::Greater => std::cmp::Ordering::Greater,
-        std::cmp::Ordering::Less => std::cmp::Ordering::Less,
-    });
+/// 判定幅の設定アセットの内容を検査して判定幅にする.
+fn judge_profile_from_asset(asset: &JudgeConfigAsset) -> Result<JudgeProfile, LoadError> {
+    let path = format!("assets/{}", JUDGE_CONFIG_PATH);
+    let config = match &asset.0 {
+        Ok(config) => config.clone(),
+        Err(e) => return Err(LoadError::new(path, LoadErrorKind::Parse(e.clone()))),
+    };
+    config
+        .validate()
+        .map_err(|message| LoadError::new(path, LoadErrorKind::InvalidJudgeConfig(message)))?;
+    Ok(JudgeProfile::from(config))
 }
 
-/// barとbeatのみの構造の列からspawn_timeとtarget_timeを持った構造の列に変換する
-pub fn to_notes_info_from_notes_spawn(
-    mut spawn_notes: Vec<NoteSpawn>,
-    speed: f32,
-    initial_bpm: f32,
-    initial_beat: u32,
-) -> Vec<NoteInfo> {
-    // ノーツをソートする.
-    sort_spawn_notes(&mut spawn_notes);
-
-    // ノーツを配列に収める
-    #[allow(unused_mut)]
-    let mut beat_par_bar = initial_beat; // 拍子
-    #[allow(unused_mut)]
-    let mut bpm = initial_bpm;
-    // 判定線への到達タイムを蓄積させる変数
-    // 途中でBPMや拍子を変更するようなイベントがあればそれを反映する.
-    // 判定線に到達する時間を曲開始時刻から測ったもの.
-    let mut target_time = 0.0;
-    let mut notes = vec![];
-    let mut prev_beat = 0.0;
+/// 譜面アセットの内容を検査して返す. 警告はログに出し, エラーがあれば読み込まない.
+pub(super) fn song_config_from_asset(
+    filename: &str,
+    asset: &ChartAsset,
+) -> Result<SongConfig, LoadError> {
+    let path = format!("assets/songs/{}", filename);
+    let config = match &asset.0 {
+        Ok(config) => config.clone(),
+        Err(e) => return Err(LoadError::new(path, LoadErrorKind::Parse(e.clone()))),
+    };
 
-    let mut prev_bar = 0u32;
-    for note in spawn_notes {
-        // このような仕様のため, 拍子を変更する場合は小節の最初に行い, かつbeat_diffの計算の前に行う.
-        // その上で, 前の拍から変更前の小節が終わるまで何拍か記憶しておき, 次の拍に足し合わせる作業が必要.
-        let beat_diff = if note.bar == prev_bar {
-            note.beat - prev_beat
-        } else {
-            // 小節番号の差を追加
-            let bar_diff = note.bar - prev_bar;
-            prev_bar = note.bar;
-            note.beat + (bar_diff * beat_par_bar) as f64 - prev_beat
-        };
-        target_time += beat_diff * (bpm as f64).recip() * 60.0;
-        let spawn_time = target_time - ((DISTANCE / speed) as f64).abs();
-        notes.push(NoteInfo {
-            note_type: note.note_type.clone(),
-            target_time,
-            spawn_time,
-            bar: note.bar,
-            beat: note.beat,
-        });
-        prev_beat = note.beat;
+    let (errors, warnings): (Vec<_>, Vec<_>) = validate_song_config(&config)
+        .into_iter()
+        .partition(ChartIssue::is_error);
+    for issue in warnings.iter() {
+        warn!("{}: {}", filename, issue);
     }
-    notes
+    if !errors.is_empty() {
+        return Err(LoadError::new(path, LoadErrorKind::InvalidChart(errors)));
+    }
+
+    Ok(config)
 }
 
-/// 指定された曲情報ファイルから曲の情報を持ったリソースを返す.
+/// 譜面データから曲の情報を持ったリソースを返す.
 fn load_song_config_resources(
-    filename: &str,
+    config: SongConfig,
     speed_coeff: f32,
     diff: &GameDifficulty,
-) -> (SongConfigResource, SongNotes, Bpm, Beat) {
+) -> (SongConfigResource, SongNotes, Bpm, Beat, LaneNum) {
     // cloneが不要になるよう全部バラしてから再構成する
     let SongConfig {
         name,
@@ -116,25 +99,28 @@ fn load_song_config_resources(
         length,
         initial_beat,
         initial_bpm,
+        offset,
+        lane_num,
         notes: mut config_notes,
-    } = load_song_config(filename);
+        keysounds,
+    } = config;
 
     let song_config_resource = SongConfigResource {
         name,
         song_filename: filename,
         length,
+        offset: offset / 1000.0,
+        keysounds,
     };
     // 小節線ノートを加える
-    let last_bar_num = if let Some(note) = config_notes.iter().last() {
-        note.bar
-    } else {
-        0
-    };
+    // 拍子変更があっても小節の頭の時刻は拍子を考慮して計算されるので, 小節番号だけ与えればよい
+    let last_bar_num = config_notes.iter().map(|note| note.bar).max().unwrap_or(0);
     for bar in 0..(last_bar_num + 2) {
         config_notes.push(NoteSpawn {
             note_type: NoteType::BarLine,
             bar,
-            beat: 0.0,
+            beat: BeatPosition::ZERO,
+            sound: None,
         })
     }
 
@@ -143,6 +129,7 @@ fn load_song_config_resources(
         speed_coeff * BASIC_NOTE_SPEED,
         initial_bpm,
         initial_beat,
+        song_config_resource.offset,
     );
 
     // Master難易度でない場合はアドリブノーツを削除する
@@ -158,22 +145,18 @@ fn load_song_config_resources(
         SongNotes(VecDeque::from_iter(notes)),
         Bpm(initial_bpm),
         Beat(initial_beat),
+        LaneNum(lane_num),
     )
 }
 
 /// アセットのロードを開始する.
 /// また, 各シーンに移行したときに用意されているべきリソース等を準備する.
-#[allow(clippy::too_many_arguments)]
+/// 曲一覧や譜面の内容に依存するリソースは, それらのアセットが読み込まれてから`prepare_*`で用意する.
 fn load_assets(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut texture_atlas: ResMut<Assets<TextureAtlas>>,
     next_scene: Res<NextAppState>,
-    mut color_material: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    selected_song: Option<Res<SongData>>,
-    speed: Option<Res<NoteSpeed>>,
-    diff: Option<Res<GameDifficulty>>,
+    selected_chart: Option<Res<ChartData>>,
 ) {
     // 型なしのアセット列を用意
     let mut assets_loading_vec = Vec::<HandleUntyped>::new();
@@ -187,49 +170,25 @@ fn load_assets(
         }
         AppState::SongSelect => {
             // 全曲データを読み込む
-            let parsed_data = load_all_config_file_data();
-            let data = parsed_data.into_iter().map(SongData::from).collect_vec();
-
-            let assets =
-                SongSelectAssetHandles::new(&asset_server, &mut texture_atlas, &mut meshes, &data);
-            // 読み込んだハンドルを型を外してクローンした配列をもらう.
-            assets_loading_vec.extend(assets.to_untyped_vec());
-            commands.insert_resource(assets);
-
-            commands.insert_resource(AllSongData(data));
-            // 難易度をここで用意しておく（選択画面でもゲーム中でも共用する）
-            commands.insert_resource(GameDifficulty::Normal);
+            let handle: Handle<SongListAsset> = asset_server.load(SONG_LIST_PATH);
+            assets_loading_vec.push(handle.clone_untyped());
+            commands.insert_resource(SongListHandle(handle));
+            commands.insert_resource(WaitingSceneData);
         }
         AppState::Game | AppState::Editor => {
             // ゲームステートに遷移する前にはこれらのリソースを用意しておかなければならない.
-            let selected_song = selected_song.unwrap();
-            let speed = speed.unwrap();
-
-            // 曲データをロード
-            let (config, notes, bpm, beat) = load_song_config_resources(
-                &selected_song.config_file_name,
-                speed.0,
-                &diff.unwrap(),
-            );
-            let music_filename = config.song_filename.clone();
-            commands.insert_resource(config);
-            commands.insert_resource(notes);
-            commands.insert_resource(bpm);
-            commands.insert_resource(beat);
+            let selected_chart = selected_chart.unwrap();
 
-            let assets = GameAssetsHandles::new(
-                music_filename,
-                &asset_server,
-                &mut texture_atlas,
-                &mut color_material,
-                &mut meshes,
-            );
-            // 読み込んだハンドルを型を外してクローンした配列をもらう.
-            assets_loading_vec.extend(assets.to_untyped_vec());
-            commands.insert_resource(assets);
-
-            // スコアリソースを初期化
-            commands.insert_resource(ScoreResource::default());
+            // 曲データを読み込む
+            let handle: Handle<ChartAsset> =
+                asset_server.load(format!("songs/{}", selected_chart.config_file_name));
+            assets_loading_vec.push(handle.clone_untyped());
+            commands.insert_resource(ChartHandle(handle));
+            // 判定幅の設定を読み込む
+            let handle: Handle<JudgeConfigAsset> = asset_server.load(JUDGE_CONFIG_PATH);
+            assets_loading_vec.push(handle.clone_untyped());
+            commands.insert_resource(JudgeConfigHandle(handle));
+            commands.insert_resource(WaitingSceneData);
         }
         _ => {}
     }
@@ -264,20 +223,166 @@ fn load_assets(
         .insert(NowLoadingText);
 }
 
+/// 曲一覧が読み込まれたら選曲画面のリソースを用意する.
+#[allow(clippy::too_many_arguments)]
+fn prepare_song_select_data(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas: ResMut<Assets<TextureAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut loading: ResMut<AssetsLoading>,
+    waiting: Option<Res<WaitingSceneData>>,
+    handle: Option<Res<SongListHandle>>,
+    song_lists: Res<Assets<SongListAsset>>,
+) {
+    let (Some(_), Some(handle)) = (waiting, handle) else {
+        return;
+    };
+    let Some(asset) = song_lists.get(&handle) else {
+        return;
+    };
+    commands.remove_resource::<WaitingSceneData>();
+
+    // 曲一覧が読めなければ選曲画面に行けないのでホームに戻す.
+    let data = match song_list_from_asset(asset) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("{}", e);
+            commands.insert_resource(e.with_back_to(AppState::HomeMenu));
+            return;
+        }
+    };
+
+    let assets = SongSelectAssetHandles::new(&asset_server, &mut texture_atlas, &mut meshes, &data);
+    // 読み込んだハンドルを型を外してクローンした配列をもらう.
+    loading.0.extend(assets.to_untyped_vec());
+    commands.insert_resource(assets);
+
+    commands.insert_resource(AllSongData(data));
+    // 難易度をここで用意しておく（選択画面でもゲーム中でも共用する）
+    commands.insert_resource(GameDifficulty::Normal);
+}
+
+/// 譜面が読み込まれたらゲーム・エディットのリソースを用意する.
+#[allow(clippy::too_many_arguments)]
+fn prepare_game_data(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas: ResMut<Assets<TextureAtlas>>,
+    mut color_material: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut loading: ResMut<AssetsLoading>,
+    waiting: Option<Res<WaitingSceneData>>,
+    handle: Option<Res<ChartHandle>>,
+    charts: Res<Assets<ChartAsset>>,
+    judge_handle: Option<Res<JudgeConfigHandle>>,
+    judge_configs: Res<Assets<JudgeConfigAsset>>,
+    selected_chart: Option<Res<ChartData>>,
+    speed: Option<Res<NoteSpeed>>,
+    diff: Option<Res<GameDifficulty>>,
+) {
+    let (Some(_), Some(handle), Some(judge_handle)) = (waiting, handle, judge_handle) else {
+        return;
+    };
+    let (Some(asset), Some(judge_asset)) = (charts.get(&handle), judge_configs.get(&judge_handle))
+    else {
+        return;
+    };
+    commands.remove_resource::<WaitingSceneData>();
+
+    let selected_chart = selected_chart.unwrap();
+    let speed = speed.unwrap();
+
+    // 曲データをロード
+    let config = match song_config_from_asset(&selected_chart.config_file_name, asset) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            commands.insert_resource(e);
+            return;
+        }
+    };
+    let judge_profile = match judge_profile_from_asset(judge_asset) {
+        Ok(profile) => profile,
+        Err(e) => {
+            error!("{}", e);
+            commands.insert_resource(e);
+            return;
+        }
+    };
+    commands.remove_resource::<JudgeConfigHandle>();
+    let (config, mut notes, bpm, beat, lane_num) =
+        load_song_config_resources(config, speed.0, &diff.unwrap());
+    let max_raw_score = max_raw_score(notes.make_contiguous());
+    let music_filename = config.song_filename.clone();
+    let keysounds = config.keysounds.clone();
+    commands.insert_resource(config);
+    commands.insert_resource(notes);
+    commands.insert_resource(bpm);
+    commands.insert_resource(beat);
+    commands.insert_resource(lane_num);
+
+    let assets = GameAssetsHandles::new(
+        music_filename,
+        &keysounds,
+        *lane_num,
+        &asset_server,
+        &mut texture_atlas,
+        &mut color_material,
+        &mut meshes,
+    );
+    // 読み込んだハンドルを型を外してクローンした配列をもらう.
+    loading.0.extend(assets.to_untyped_vec());
+    commands.insert_resource(assets);
+
+    // スコアリソースを初期化. 使った判定幅を記録しておく.
+    commands.insert_resource(ScoreResource::new(max_raw_score, &judge_profile.name));
+    commands.insert_resource(judge_profile);
+}
+
 fn check_assets_ready(
+    mut commands: Commands,
     mut state: ResMut<State<AppState>>,
     server: Res<AssetServer>,
     loading: Res<AssetsLoading>,
     next_scene: Res<NextAppState>,
+    load_error: Option<Res<LoadError>>,
+    waiting: Option<Res<WaitingSceneData>>,
 ) {
+    // 曲一覧や譜面の読み込みに失敗していたらエラー画面へ移行する
+    if load_error.is_some() {
+        state.set(AppState::LoadError).unwrap();
+        return;
+    }
     // すべてロードが終わったかどうかを確認してから次のシーンへ移行する
     match server.get_group_load_state(loading.0.iter().map(|h| h.id)) {
         // ここでローディングテキストや画像を動かしてもいい.
         LoadState::Loading => {}
         LoadState::Failed => {
-            warn!("loading failed");
+            // どのアセットで失敗したかを調べてエラー画面に表示する
+            let path = loading
+                .0
+                .iter()
+                .find(|h| matches!(server.get_load_state(h.id), LoadState::Failed))
+                .and_then(|h| server.get_handle_path(h.id))
+                .map(|path| path.path().display().to_string())
+                .unwrap_or_default();
+            let error = LoadError::new(path, LoadErrorKind::Asset);
+            error!("{}", error);
+            // 選曲画面自体のアセットが読めない場合はホームに戻す
+            let error = if matches!(next_scene.0, AppState::SongSelect) {
+                error.with_back_to(AppState::HomeMenu)
+            } else {
+                error
+            };
+            commands.insert_resource(error);
+            state.set(AppState::LoadError).unwrap();
         }
         LoadState::Loaded => {
+            // 曲一覧や譜面の内容に依存するアセットがまだ追加されていない
+            if waiting.is_some() {
+                return;
+            }
             info!("loaded");
             // ロード完了したら次のシーンに遷移する命令
             state.set(next_scene.0).unwrap();
@@ -297,12 +402,75 @@ fn exit_loading(mut commands: Commands, text_q: Query<Entity, With<NowLoadingTex
     }
 }
 
+/// 選曲画面で曲一覧ファイルが変更されたら読み込み直す.
+fn reload_song_list(
+    mut commands: Commands,
+    mut ev_reader: EventReader<AssetEvent<SongListAsset>>,
+    handle: Res<SongListHandle>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let modified = ev_reader.iter().any(|ev| match ev {
+        AssetEvent::Modified { handle: modified } => *modified == **handle,
+        _ => false,
+    });
+    if modified {
+        info!("song list is modified. reloading...");
+        commands.insert_resource(NextAppState(AppState::SongSelect));
+        state.set(AppState::Loading).unwrap();
+    }
+}
+
+/// ゲーム中に譜面ファイルが変更されたら読み込み直して最初から始める.
+fn reload_chart(
+    mut commands: Commands,
+    mut ev_reader: EventReader<AssetEvent<ChartAsset>>,
+    handle: Res<ChartHandle>,
+    mut state: ResMut<State<AppState>>,
+    mut panic_audio_ev_writer: EventWriter<PanicAudio>,
+) {
+    let modified = ev_reader.iter().any(|ev| match ev {
+        AssetEvent::Modified { handle: modified } => *modified == **handle,
+        _ => false,
+    });
+    if modified {
+        info!("chart is modified. restarting...");
+        panic_audio_ev_writer.send(PanicAudio);
+        commands.remove_resource::<ResultDisplayed>();
+        commands.insert_resource(NextAppState(AppState::Game));
+        state.set(AppState::Loading).unwrap();
+    }
+}
+
 pub struct LoadPlugin;
 impl Plugin for LoadPlugin {
     fn build(&self, app: &mut App) {
+        // 譜面と曲一覧, 判定幅の設定をアセットとして扱う
+        app.add_asset::<ChartAsset>()
+            .init_asset_loader::<ChartAssetLoader>()
+            .add_asset::<JudgeConfigAsset>()
+            .init_asset_loader::<JudgeConfigAssetLoader>()
+            .add_asset::<SongListAsset>()
+            .init_asset_loader::<SongListAssetLoader>();
         // アセットロード関連システム
         add_enter_system!(app, Loading, load_assets);
-        add_update_system!(app, Loading, check_assets_ready);
+        add_update_system!(
+            app,
+            Loading,
+            prepare_song_select_data,
+            [],
+            LoadSystemLabel::Prepare
+        );
+        add_update_system!(
+            app,
+            Loading,
+            prepare_game_data,
+            [],
+            LoadSystemLabel::Prepare
+        );
+        add_update_system!(app, Loading, check_assets_ready, [after: LoadSystemLabel::Prepare]);
         add_exit_system!(app, Loading, exit_loading);
+        // ファイルの変更を検知して読み込み直す
+        add_update_system!(app, SongSelect, reload_song_list);
+        add_update_system!(app, Game, reload_chart);
     }
 }
@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+use itertools::Itertools;
+
+use crate::{
+    add_enter_system, add_exit_system, add_update_system,
+    components::load::LoadErrorObject,
+    constants::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    resources::{game_state::NextAppState, load_error::LoadError},
+    spawn_text_node, AppState,
+};
+
+/// エラー画面に表示する詳細の最大行数
+const MAX_DETAIL_LINES: usize = 12;
+
+/// 読み込みエラーの内容を表示する
+fn setup_error_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    load_error: Res<LoadError>,
+) {
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.1, 0.1, 0.1),
+                custom_size: Some(Vec2::new(SCREEN_WIDTH, SCREEN_HEIGHT)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(LoadErrorObject);
+
+    let location = match load_error.location() {
+        Some((line, column)) => format!("line {}, column {}", line, column),
+        None => "-".to_string(),
+    };
+    // 譜面の検査エラーは行数が多くなるので途中で打ち切る
+    let detail = load_error.to_string();
+    let line_num = detail.lines().count();
+    let mut detail = detail.lines().take(MAX_DETAIL_LINES).join("\n");
+    if line_num > MAX_DETAIL_LINES {
+        detail.push_str(&format!("\n... and {} more", line_num - MAX_DETAIL_LINES));
+    }
+    let font: Handle<Font> = asset_server.load("fonts/FiraSans-Bold.ttf");
+    spawn_text_node!(
+        commands,
+        font,
+        [left: 20.0, top: 20.0],
+        Color::NONE,
+        [
+            ["Failed to load", 40.0, Color::RED, []],
+            [format!("file: {}", load_error.path), 24.0, Color::WHITE, []],
+            [format!("location: {}", location), 24.0, Color::WHITE, []],
+            [detail, 18.0, Color::ANTIQUE_WHITE, []],
+            [format!("Press Z to return to {:?}", load_error.back_to), 24.0, Color::GRAY, []]
+        ],
+        [LoadErrorObject],
+        { max_size: Size::new(Val::Px(SCREEN_WIDTH - 40.0), Val::Undefined) }
+    );
+}
+
+/// 決定キーで戻る
+fn back_from_error_screen(
+    mut commands: Commands,
+    mut key_input: ResMut<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+    load_error: Res<LoadError>,
+) {
+    if key_input.any_just_pressed([KeyCode::Z, KeyCode::Return]) {
+        key_input.reset_all();
+        commands.insert_resource(NextAppState(load_error.back_to));
+        state.set(AppState::Loading).unwrap();
+    }
+}
+
+fn despawn_error_screen(mut commands: Commands, q: Query<Entity, With<LoadErrorObject>>) {
+    for ent in q.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    commands.remove_resource::<LoadError>();
+}
+
+pub struct LoadErrorPlugin;
+impl Plugin for LoadErrorPlugin {
+    fn build(&self, app: &mut App) {
+        add_enter_system!(app, LoadError, setup_error_screen);
+        add_update_system!(app, LoadError, back_from_error_screen);
+        add_exit_system!(app, LoadError, despawn_error_screen);
+    }
+}
@@ -2,6 +2,7 @@ pub mod audio;
 pub mod editor;
 pub mod home_menu;
 pub mod load;
+pub mod load_error;
 pub mod note;
 pub mod receptor;
 pub mod result_screen;
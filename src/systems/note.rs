@@ -2,14 +2,14 @@ use bevy::prelude::*;
 use bevy::time::FixedTimestep;
 
 use crate::components::note::{KeyLane, LongNote, LongNoteState, MissingNote, NoteInfo};
-use crate::components::timer::FrameCounter;
-use crate::constants::{BASIC_NOTE_SPEED, FRAMERATE, MISS_THR, TARGET_Y};
+use crate::constants::{BASIC_NOTE_SPEED, FRAMERATE, ROLL_TAP_INTERVAL, TARGET_Y};
 use crate::events::{CatchNoteEvent, NoteEvalEvent};
 use crate::resources::note::NoteType;
 use crate::resources::{
-    config::{Beat, Bpm, NoteSpeed},
+    config::{Beat, Bpm, LaneNum, NoteSpeed},
     handles::GameAssetsHandles,
-    score::CatchEval,
+    judge::{JudgeProfile, JudgeWindow},
+    score::{CatchEval, TimingEval},
     song::{SongNotes, SongStartTime},
 };
 use crate::{add_update_system, AppState};
@@ -23,7 +23,7 @@ fn spawn_notes(
     notes: Option<ResMut<SongNotes>>,
     start_time: Option<Res<SongStartTime>>,
     speed: Option<Res<NoteSpeed>>,
-    bpm: Option<Res<Bpm>>,
+    lane_num: Option<Res<LaneNum>>,
     time: Option<Res<Time>>,
     mut color_material: ResMut<Assets<ColorMaterial>>,
     state: Res<State<AppState>>,
@@ -39,7 +39,7 @@ fn spawn_notes(
     let start_time = start_time.unwrap();
     let time = time.unwrap();
     let speed = speed.unwrap();
-    let bpm = bpm.unwrap();
+    let lane_num = lane_num.unwrap();
 
     // 現在スタートから何秒経ったかと前の処理が何秒だったかを取得する.
     let time_after_start = start_time.time_after_start(&time);
@@ -60,7 +60,8 @@ fn spawn_notes(
             &mut color_material,
             &note.note_type,
             **speed,
-            **bpm,
+            note.end_time - note.target_time,
+            **lane_num,
             false,
         );
         let is_long_note = matches!(
@@ -69,15 +70,17 @@ fn spawn_notes(
                 key: _,
                 length: _,
                 id: _
+            } | NoteType::Roll {
+                key: _,
+                length: _,
+                id: _
             }
         );
         let note_bundle = (note, note_mesh);
 
         let ent = commands.spawn(note_bundle).id();
         if is_long_note {
-            commands
-                .entity(ent)
-                .insert((LongNote::new(), FrameCounter::new()));
+            commands.entity(ent).insert(LongNote::new());
         }
     }
 }
@@ -87,20 +90,18 @@ fn move_notes(
     time: Res<Time>,
     mut query: Query<(&mut Transform, &NoteInfo, Option<&MissingNote>, Entity)>,
     speed: Res<NoteSpeed>,
+    judge: Res<JudgeProfile>,
     mut eval_ev_writer: EventWriter<NoteEvalEvent>,
 ) {
     for (mut transform, note, missing, ent) in query.iter_mut() {
         transform.translation.y -= time.delta_seconds() * speed.0 * BASIC_NOTE_SPEED;
-        let allow_distance = MISS_THR as f32 * BASIC_NOTE_SPEED * speed.0;
+        let allow_distance = judge.window(&note.note_type).ok as f32 * BASIC_NOTE_SPEED * speed.0;
         let distance_after_target = transform.translation.y - (TARGET_Y - allow_distance);
         // ミス処理を行うノーツのタイプを選択する
         if matches!(note.note_type, NoteType::Normal { key: _ }) && distance_after_target < -0.02 {
             if missing.is_none() {
                 // ミスが確定したときにコンポーネントを付与しつつイベント送信
-                eval_ev_writer.send(NoteEvalEvent {
-                    eval: CatchEval::Miss,
-                    note: note.clone(),
-                });
+                eval_ev_writer.send(NoteEvalEvent::head(note, CatchEval::Miss));
                 commands.entity(ent).insert(MissingNote);
             }
             transform.rotate_axis(Vec3::Z, 0.1);
@@ -111,17 +112,31 @@ fn move_notes(
     }
 }
 
-/// ロングノーツの演出
+/// ロングノーツの演出. ロールノーツは橙色系で区別する.
 fn long_note_operation(
-    q: Query<(&Handle<ColorMaterial>, &LongNote)>,
+    q: Query<(&Handle<ColorMaterial>, &LongNote, &NoteInfo)>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    for (color_handle, note) in q.iter() {
+    for (color_handle, note, info) in q.iter() {
         let color = &mut materials.get_mut(color_handle).unwrap().color;
+        let is_roll = matches!(
+            info.note_type,
+            NoteType::Roll {
+                key: _,
+                length: _,
+                id: _
+            }
+        );
         match note.state {
+            LongNoteState::BeforeRetrieve if is_roll => {
+                *color = Color::rgba(1.0, 0.6, 0.0, 0.7);
+            }
             LongNoteState::BeforeRetrieve => {
                 *color = Color::rgba(1.0, 1.0, 1.0, 0.7);
             }
+            LongNoteState::Hold | LongNoteState::End if is_roll => {
+                *color = Color::YELLOW;
+            }
             LongNoteState::Hold | LongNoteState::End => {
                 *color = Color::CYAN;
             }
@@ -144,6 +159,7 @@ fn catch_notes(
     time: Res<Time>,
     bpm: Res<Bpm>,
     beat: Res<Beat>,
+    judge: Res<JudgeProfile>,
 ) {
     let time_after_start = start_time.time_after_start(&time);
     // despawnはクエリには影響しないため, 重複したキーで一つのノーツを複数回取れてしまう.
@@ -154,35 +170,53 @@ fn catch_notes(
             let note_target_time = note.target_time;
             // 現在時刻が許容範囲・鍵盤番号が一致・キーがちょうど押された・まだ消去されていないノートを取得処理
             let note_caught = match note.note_type {
-                NoteType::Normal { key } => key == lane.0,
+                NoteType::Normal { key } => key == lane.num,
                 NoteType::BarLine => false,
-                NoteType::AdLib { key } => key == lane.0,
-                // ロングノーツはここでは扱わない
+                NoteType::AdLib { key } => key == lane.num,
+                // ロングノーツとロールノーツはここでは扱わない
                 NoteType::Long {
                     key: _,
                     length: _,
                     id: _,
+                }
+                | NoteType::Roll {
+                    key: _,
+                    length: _,
+                    id: _,
                 } => false,
+                NoteType::BpmChange { bpm: _ }
+                | NoteType::BeatChange { beat: _ }
+                | NoteType::KeySound { sound: _ } => false,
+                // 地雷は取得すると減点になる
+                NoteType::Mine { key } => key == lane.num,
             };
-            if (note_target_time - MISS_THR..=note_target_time + MISS_THR)
-                .contains(&time_after_start)
+            let window = judge.window(&note.note_type);
+            if window.contains(note_target_time, time_after_start)
                 && note_caught
                 && lane.key_just_pressed(&key_input)
                 && !retrieved_notes.contains(&ent)
             {
                 commands.entity(ent).despawn();
                 retrieved_notes.push(ent);
+                if let NoteType::Mine { key: _ } = note.note_type {
+                    // パターンの判定には含めない
+                    eval_ev_writer.send(NoteEvalEvent::head(note, CatchEval::Mine));
+                    continue;
+                }
                 catch_ev_writer.send(CatchNoteEvent::new(note, time_after_start, **bpm, **beat));
-                eval_ev_writer.send(NoteEvalEvent::new(note, time_after_start));
+                eval_ev_writer.send(NoteEvalEvent::new(note, time_after_start, &window));
             }
         }
     }
 }
 
-/// ロングノーツの取得処理はこちら
+/// ロングノーツの取得処理はこちら.
+/// 始点は押した時刻で, 終点は離した時刻で評価する.
+/// 押している間は始点から拍を等分した時刻ごとに加点し, 途中で離して失敗した後の残りはミスとする.
+/// ロールノーツも同じ流れで処理し, ホールド中に押し続ける代わりに一定の間隔以内で叩き直すことを成功条件とする.
 #[allow(clippy::too_many_arguments)]
 fn catch_long_notes(
-    mut note_q: Query<(&NoteInfo, &mut LongNote, &mut FrameCounter)>,
+    mut note_q: Query<(&NoteInfo, &mut LongNote)>,
     mut lane_q: Query<&KeyLane>,
     key_input: Res<Input<KeyCode>>,
     mut catch_ev_writer: EventWriter<CatchNoteEvent>,
@@ -191,23 +225,35 @@ fn catch_long_notes(
     time: Res<Time>,
     bpm: Res<Bpm>,
     beat: Res<Beat>,
+    judge: Res<JudgeProfile>,
 ) {
     let time_after_start = start_time.time_after_start(&time);
     for lane in lane_q.iter_mut() {
-        for (note, mut long_note, mut counter) in note_q.iter_mut() {
+        for (note, mut long_note) in note_q.iter_mut() {
             // ロングノーツでない場合飛ばす（クエリの制限により基本的にありえないはずだが）
-            let NoteType::Long { key, length, id: _} = note.note_type else { continue };
+            let (key, is_roll) = match note.note_type {
+                NoteType::Long {
+                    key,
+                    length: _,
+                    id: _,
+                } => (key, false),
+                NoteType::Roll {
+                    key,
+                    length: _,
+                    id: _,
+                } => (key, true),
+                _ => continue,
+            };
             // キーとレーンが異なる場合は処理しない.
-            if key != lane.0 {
+            if key != lane.num {
                 continue;
             }
             // ロングノーツの場合は始点の到着時刻
             let note_target_time = note.target_time;
-            let note_end_time = note_target_time + (length / **bpm * 60.0) as f64;
+            let window = judge.window(&note.note_type);
             match long_note.state {
                 LongNoteState::BeforeRetrieve => {
-                    if (note_target_time - MISS_THR..=note_target_time + MISS_THR)
-                        .contains(&time_after_start)
+                    if window.contains(note_target_time, time_after_start)
                         && lane.key_just_pressed(&key_input)
                     {
                         // 現在時刻が許容範囲・鍵盤番号が一致・キーがちょうど押されたら始点の取得処理
@@ -217,61 +263,133 @@ fn catch_long_notes(
                             **bpm,
                             **beat,
                         ));
-                        eval_ev_writer.send(NoteEvalEvent::new(note, time_after_start));
+                        eval_ev_writer.send(NoteEvalEvent::new(note, time_after_start, &window));
                         long_note.state = LongNoteState::Hold;
-                    } else if time_after_start > note_target_time + MISS_THR {
+                        long_note.last_tap = time_after_start;
+                    } else if time_after_start > note_target_time + window.ok {
+                        // 始点を逃したら終点もミスとする
+                        eval_ev_writer.send(NoteEvalEvent::head(note, CatchEval::Miss));
+                        eval_ev_writer.send(NoteEvalEvent::tail(note, CatchEval::Miss));
                         long_note.state = LongNoteState::Miss;
-                    } else if time_after_start > note_target_time {
-                        // ちょうど到達したときにカウンターをリセットする
-                        counter.reset();
                     }
                 }
-                LongNoteState::Hold | LongNoteState::Miss => {
-                    if (counter.count() + 1) % 12 == 0
-                        && (note_target_time..=note_end_time).contains(&time_after_start)
+                LongNoteState::Hold | LongNoteState::Miss | LongNoteState::End => {
+                    if matches!(long_note.state, LongNoteState::Hold) {
+                        if let Some(tail_eval) = judge_tail(
+                            &mut long_note,
+                            lane,
+                            &key_input,
+                            is_roll,
+                            &window,
+                            note,
+                            time_after_start,
+                        ) {
+                            eval_ev_writer.send(NoteEvalEvent::tail(note, tail_eval));
+                        }
+                    }
+                    // 加点の時刻は読み込み時に求めてある. 終点ちょうどは終点の評価に含める.
+                    while note
+                        .tick_times
+                        .get(long_note.next_tick as usize)
+                        .is_some_and(|tick_time| *tick_time <= time_after_start)
                     {
-                        // これで確実に同じタイミングで取得かミスか判定される
+                        long_note.next_tick += 1;
                         match long_note.state {
-                            LongNoteState::Hold => {
-                                if lane.key_pressed(&key_input) {
-                                    // 押しっぱなしでホールド中なら一定間隔で加点
-                                    catch_ev_writer.send(CatchNoteEvent::new(
-                                        note,
-                                        time_after_start,
-                                        **bpm,
-                                        **beat,
-                                    ));
-                                    eval_ev_writer.send(NoteEvalEvent {
-                                        eval: CatchEval::Perfect,
-                                        note: note.clone(),
-                                    });
-                                } else if lane.key_just_released(&key_input) {
-                                    // 離された場合は終点以降かどうかチェックして分岐
-                                    if time_after_start > note_end_time - MISS_THR {
-                                        long_note.state = LongNoteState::End;
-                                    } else {
-                                        long_note.state = LongNoteState::Miss;
-                                    }
-                                } else {
-                                    long_note.state = LongNoteState::Miss;
-                                }
+                            LongNoteState::Hold | LongNoteState::End => {
+                                catch_ev_writer.send(CatchNoteEvent::new(
+                                    note,
+                                    time_after_start,
+                                    **bpm,
+                                    **beat,
+                                ));
+                                eval_ev_writer.send(NoteEvalEvent::tick(note, CatchEval::Perfect));
                             }
-                            LongNoteState::Miss => {
-                                eval_ev_writer.send(NoteEvalEvent {
-                                    eval: CatchEval::Miss,
-                                    note: note.clone(),
-                                });
+                            _ => {
+                                eval_ev_writer.send(NoteEvalEvent::tick(note, CatchEval::Miss));
                             }
-                            _ => {}
                         }
                     }
                 }
-                LongNoteState::End => {}
             }
         }
     }
 }
 
+/// ホールド中のロングノーツ・ロールノーツの終点を判定し, 状態を進める. 判定が確定したら終点の評価を返す.
+/// ロングノーツは離した時刻を終点の時刻と比べて評価する. 判定の範囲を過ぎても押し続けていた場合は遅れたものとする.
+/// ロールノーツは間隔を空けずに叩き続けて終点に到達すれば成功とする.
+fn judge_tail(
+    long_note: &mut LongNote,
+    lane: &KeyLane,
+    key_input: &Input<KeyCode>,
+    is_roll: bool,
+    window: &JudgeWindow,
+    note: &NoteInfo,
+    time_after_start: f64,
+) -> Option<CatchEval> {
+    let note_end_time = note.end_time;
+    let eval = if is_roll {
+        if lane.key_just_pressed(key_input) {
+            long_note.last_tap = time_after_start;
+        }
+        // 叩き直す間隔は拍で決まるので, 途中でBPMが変わると秒数も変わる
+        let tap_deadline = note.time_after_beats(long_note.last_tap, ROLL_TAP_INTERVAL as f64);
+        if time_after_start >= note_end_time {
+            CatchEval::Perfect
+        } else if time_after_start < note_end_time - window.ok && time_after_start > tap_deadline {
+            CatchEval::Miss
+        } else {
+            return None;
+        }
+    } else if !lane.key_pressed(key_input) {
+        if time_after_start < note_end_time - window.ok {
+            // 早く離しすぎた
+            CatchEval::Miss
+        } else {
+            window.eval(note_end_time, time_after_start)
+        }
+    } else if time_after_start > note_end_time + window.ok {
+        CatchEval::Ok(TimingEval::Slow)
+    } else {
+        return None;
+    };
+    long_note.state = if matches!(eval, CatchEval::Miss) {
+        LongNoteState::Miss
+    } else {
+        LongNoteState::End
+    };
+    Some(eval)
+}
+
+/// BPM変更・拍子変更イベントが判定線に到達したらリソースを更新する
+fn execute_event_notes(
+    mut commands: Commands,
+    note_q: Query<(&NoteInfo, Entity)>,
+    start_time: Res<SongStartTime>,
+    time: Res<Time>,
+    mut bpm: ResMut<Bpm>,
+    mut beat: ResMut<Beat>,
+) {
+    let time_after_start = start_time.time_after_start(&time);
+    for (note, ent) in note_q.iter() {
+        if time_after_start < note.target_time {
+            continue;
+        }
+        match note.note_type {
+            NoteType::BpmChange { bpm: new_bpm } => {
+                info!("bpm change: {} -> {}", **bpm, new_bpm);
+                **bpm = new_bpm;
+            }
+            NoteType::BeatChange { beat: new_beat } => {
+                info!("beat change: {} -> {}", **beat, new_beat);
+                **beat = new_beat;
+            }
+            _ => continue,
+        }
+        commands.entity(ent).despawn();
+    }
+}
+
 /// 取得しなかった等で画面外に出たノーツを消去する処理
 #[allow(clippy::too_many_arguments)]
 fn drop_notes(mut commands: Commands, query: Query<(&Transform, &NoteInfo, Entity)>) {
@@ -297,6 +415,7 @@ impl Plugin for NotePlugin {
         add_update_system!(app, Game, move_notes);
         add_update_system!(app, Game, catch_notes);
         add_update_system!(app, Game, catch_long_notes);
+        add_update_system!(app, Game, execute_event_notes);
         add_update_system!(app, Game, drop_notes);
     }
 }
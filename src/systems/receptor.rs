@@ -4,7 +4,7 @@ use crate::{
     components::receptor::{prelude::*, PatternReceptor, PatternReceptorMarker},
     events::{AchievePatternEvent, CatchNoteEvent},
     resources::{
-        config::{Bpm, GameDifficulty},
+        config::{Bpm, GameDifficulty, LaneNum},
         score::ScoreResource,
         song::SongStartTime,
     },
@@ -13,12 +13,12 @@ use crate::{
 };
 
 /// 難易度Expert, Masterの場合はレセプタをここで登録.
-fn setup_receptor(mut commands: Commands, diff: Res<GameDifficulty>) {
+fn setup_receptor(mut commands: Commands, diff: Res<GameDifficulty>, lane_num: Res<LaneNum>) {
     /// PatternReceptorを実装した構造体を入れる.
     macro_rules! spawn_receptor {
         ($x:ty) => {
             commands.spawn((
-                <$x>::default(),
+                <$x>::with_lane_num(**lane_num),
                 PatternReceptorMarker(<$x>::NAME.to_string()),
             ));
         };
@@ -2,13 +2,19 @@ use bevy::prelude::*;
 
 use crate::{
     add_exit_system, add_update_system,
-    components::{note::NoteInfo, result_screen::ScrollingList, ui::GameStateObject},
+    components::{
+        note::NoteInfo,
+        result_screen::{ScrollingList, TimingStatsPanel},
+        ui::GameStateObject,
+    },
     events::PanicAudio,
     resources::{
         game_state::{ExistingEntities, NextAppState, ResultDisplayed},
         handles::GameAssetsHandles,
-        score::{CatchEval, ScoreResource, TimingEval},
-        song::{SongConfigResource, SongStartTime},
+        judge::{JudgeProfile, JudgeWindow},
+        life_gauge::LifeGauge,
+        score::{CatchEval, Grade, JudgePoint, ScoreResource, TimingEval},
+        song::{SongConfigResource, SongNotes, SongStartTime},
     },
     AppState, SCREEN_HEIGHT, SCREEN_WIDTH,
 };
@@ -16,11 +22,14 @@ use crate::{
 #[allow(clippy::too_many_arguments)]
 fn spawn_result(
     mut commands: Commands,
-    notes_q: Query<&NoteInfo>,
+    notes_q: Query<Entity, With<NoteInfo>>,
+    mut song_notes: ResMut<SongNotes>,
     song_config: Res<SongConfigResource>,
     start_time: Res<SongStartTime>,
     time: Res<Time>,
     score: Res<ScoreResource>,
+    gauge: Res<LifeGauge>,
+    judge: Res<JudgeProfile>,
     handles: Res<GameAssetsHandles>,
     // すでに出現したかどうか
     spawned: Option<Res<ResultDisplayed>>,
@@ -32,12 +41,18 @@ fn spawn_result(
     }
     let time_after_start = start_time.time_after_start(&time);
     let song_length = song_config.length;
-    // ノーツが全部消えてかつ曲尺を2秒超えたらリザルト画面に移行
-    if notes_q.is_empty() && song_length + 2.0 < time_after_start {
+    // ノーツが全部消えてかつ曲尺を2秒超えたらリザルト画面に移行. ゲージが空になった場合はその時点で移行する.
+    let cleared = notes_q.is_empty() && song_length + 2.0 < time_after_start;
+    if cleared || gauge.is_failed() {
         // ゲームエンティティの片付け
         for ent in game_obj_q.iter() {
             commands.entity(ent).despawn_recursive();
         }
+        // 途中で終了した場合は残りのノーツも片付ける
+        for ent in notes_q.iter() {
+            commands.entity(ent).despawn_recursive();
+        }
+        song_notes.clear();
         // 音を停止
         panic_audio_ev_writer.send(PanicAudio);
         // リザルト表示
@@ -62,30 +77,94 @@ fn spawn_result(
                 ..Default::default()
             })
             .with_children(|parent| {
-                let text = format!(
-                    "Score: {}.\n\n\tPerfect: {}.\n\tOk: {}.\n\tMiss: {}.",
-                    score.get_score(),
+                let mut text = format!(
+                    "\n\n\tPerfect: {}.\n\tOk: {}.\n\tMiss: {}.\n\tMine: {}.",
                     score.get_eval_num(&CatchEval::Perfect)
                         + score.get_eval_num(&CatchEval::NearPerfect(TimingEval::Fast))
                         + score.get_eval_num(&CatchEval::NearPerfect(TimingEval::Slow)),
                     score.get_eval_num(&CatchEval::Ok(TimingEval::Fast))
                         + score.get_eval_num(&CatchEval::Ok(TimingEval::Slow)),
                     score.get_eval_num(&CatchEval::Miss) + score.get_eval_num(&CatchEval::Miss),
+                    score.get_eval_num(&CatchEval::Mine),
                 );
-                parent.spawn(TextBundle {
-                    text: Text {
-                        sections: vec![TextSection {
-                            value: text,
-                            style: TextStyle {
-                                font: handles.main_font.clone(),
-                                font_size: 60.0,
-                                color: Color::DARK_GRAY,
-                            },
-                        }],
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                });
+                text.push_str(&format!("\n\tMax Combo: {}.", score.get_max_combo()));
+                // ロングノーツがあれば始点と終点の評価から求めた精度を表示する
+                if let Some(accuracy) = score.get_hold_accuracy() {
+                    let tail_miss =
+                        score.get_long_note_eval_num(JudgePoint::Tail, &CatchEval::Miss);
+                    text.push_str(&format!(
+                        "\n\tHold: {:.1}% (tail miss: {}).",
+                        accuracy * 100.0,
+                        tail_miss
+                    ));
+                }
+                text.push_str(&format!(
+                    "\n\tGauge: {}{}: {:.0}%.",
+                    gauge.get_gauge_type(),
+                    if gauge.is_no_fail() { " (No Fail)" } else { "" },
+                    gauge.get_value() * 100.0
+                ));
+                // 叩いた時刻の誤差の平均と標準偏差. 詳細はTキーで表示する.
+                if let Some(stats) = score.get_timing_stats() {
+                    text.push_str(&format!(
+                        "\n\tTiming: {:+.1} ms (SD {:.1} ms, fast {} / slow {}). [T]",
+                        stats.mean * 1000.0,
+                        stats.std_dev * 1000.0,
+                        stats.fast,
+                        stats.slow
+                    ));
+                }
+                text.push_str(&format!("\n\tJudge: {}.", score.get_judge_profile()));
+                // 失敗, フルコンボ, オールパーフェクトのバッジはスコアの横に色を変えて表示する
+                let (badge, badge_color) = if gauge.is_failed() {
+                    ("  Failed", Color::RED)
+                } else if gauge.is_emptied() {
+                    ("  Failed (No Fail)", Color::RED)
+                } else if score.is_all_perfect() {
+                    ("  All Perfect!", Color::GOLD)
+                } else if score.is_full_combo() {
+                    ("  Full Combo!", Color::ORANGE_RED)
+                } else {
+                    ("", Color::NONE)
+                };
+                let style = TextStyle {
+                    font: handles.main_font.clone(),
+                    font_size: 40.0,
+                    color: Color::DARK_GRAY,
+                };
+                // 精度から決まる評価を大きく表示する. 何も評価されていなければDとする.
+                let accuracy = score.get_accuracy().unwrap_or(0.0);
+                let grade = score.get_grade().unwrap_or(Grade::D);
+                parent.spawn(TextBundle::from_sections([
+                    TextSection::new(
+                        grade.to_string(),
+                        TextStyle {
+                            font_size: 100.0,
+                            color: grade.get_color(),
+                            ..style.clone()
+                        },
+                    ),
+                    TextSection::new(
+                        format!("  Accuracy: {:.2}%\n", accuracy * 100.0),
+                        style.clone(),
+                    ),
+                    TextSection::new(
+                        format!(
+                            "Score: {} (pattern bonus: +{}).",
+                            score.get_score(),
+                            score.get_pattern_bonus()
+                        ),
+                        style.clone(),
+                    ),
+                    TextSection::new(
+                        badge,
+                        TextStyle {
+                            color: badge_color,
+                            ..style.clone()
+                        },
+                    ),
+                    TextSection::new(text, style),
+                ]));
             });
 
         let pattern_vec = score.get_pattern_vec();
@@ -175,6 +254,161 @@ fn spawn_result(
                         });
                 });
         }
+
+        spawn_timing_stats_panel(
+            &mut commands,
+            &score,
+            &judge.base_window(),
+            song_length,
+            &handles.main_font,
+        );
+    }
+}
+
+/// 誤差の分布の区間数. 真ん中の区間が誤差0を含むよう奇数にする.
+const HISTOGRAM_BIN_NUM: usize = 21;
+/// 誤差のグラフの大きさ（px）
+const GRAPH_WIDTH: f32 = 700.0;
+const GRAPH_HEIGHT: f32 = 180.0;
+/// 誤差の推移を表す点の大きさ（px）
+const SCATTER_POINT_SIZE: f32 = 4.0;
+
+/// タイミングの詳細パネルを非表示の状態で出現させる.
+/// 上段は誤差の分布（左が早い）, 下段は曲中の誤差の推移（上が早い）で, 色は評価に対応させる.
+fn spawn_timing_stats_panel(
+    commands: &mut Commands,
+    score: &ScoreResource,
+    window: &JudgeWindow,
+    song_length: f64,
+    font: &Handle<Font>,
+) {
+    let range = window.ok;
+    let histogram = score.get_timing_histogram(range, HISTOGRAM_BIN_NUM);
+    let max_count = histogram.iter().copied().max().unwrap_or(0).max(1);
+    let bin_width = 2.0 * range / HISTOGRAM_BIN_NUM as f64;
+    let title_style = TextStyle {
+        font: font.clone(),
+        font_size: 30.0,
+        color: Color::DARK_GRAY,
+    };
+    let graph_style = Style {
+        size: Size::new(Val::Px(GRAPH_WIDTH), Val::Px(GRAPH_HEIGHT)),
+        margin: UiRect::all(Val::Px(10.0)),
+        ..default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            background_color: Color::AZURE.into(),
+            z_index: ZIndex::Global(1),
+            ..default()
+        })
+        .insert(TimingStatsPanel)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                format!("Timing (±{:.0} ms)", range * 1000.0),
+                title_style.clone(),
+            ));
+            // 誤差の分布. 区間の真ん中の誤差で評価した色で塗る.
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::FlexEnd,
+                        ..graph_style.clone()
+                    },
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (i, count) in histogram.iter().enumerate() {
+                        let center = -range + (i as f64 + 0.5) * bin_width;
+                        parent.spawn(NodeBundle {
+                            style: Style {
+                                size: Size::new(
+                                    Val::Percent(100.0 / HISTOGRAM_BIN_NUM as f32),
+                                    Val::Percent(*count as f32 / max_count as f32 * 100.0),
+                                ),
+                                ..default()
+                            },
+                            background_color: window.eval(0.0, center).get_color().into(),
+                            ..default()
+                        });
+                    }
+                });
+            // 曲中の誤差の推移. 真ん中の線が誤差0.
+            parent
+                .spawn(NodeBundle {
+                    style: graph_style,
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Px(0.0),
+                                top: Val::Percent(50.0),
+                                ..default()
+                            },
+                            size: Size::new(Val::Percent(100.0), Val::Px(1.0)),
+                            ..default()
+                        },
+                        background_color: Color::GRAY.into(),
+                        ..default()
+                    });
+                    for (target_time, error) in score.get_timing_errors().iter() {
+                        let x = (target_time / song_length.max(1.0)).clamp(0.0, 1.0);
+                        let y = ((error + range) / (2.0 * range)).clamp(0.0, 1.0);
+                        parent.spawn(NodeBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                position: UiRect {
+                                    left: Val::Percent(x as f32 * 100.0),
+                                    top: Val::Percent(y as f32 * 100.0),
+                                    ..default()
+                                },
+                                size: Size::new(
+                                    Val::Px(SCATTER_POINT_SIZE),
+                                    Val::Px(SCATTER_POINT_SIZE),
+                                ),
+                                ..default()
+                            },
+                            background_color: window.eval(0.0, *error).get_color().into(),
+                            ..default()
+                        });
+                    }
+                });
+            parent.spawn(TextBundle::from_section(
+                "Press T to close",
+                TextStyle {
+                    font_size: 20.0,
+                    ..title_style
+                },
+            ));
+        });
+}
+
+/// Tキーでタイミングの詳細パネルの表示を切り替える
+fn toggle_timing_stats(
+    key_input: Res<Input<KeyCode>>,
+    mut panel_q: Query<&mut Style, With<TimingStatsPanel>>,
+) {
+    if key_input.just_pressed(KeyCode::T) {
+        for mut style in panel_q.iter_mut() {
+            style.display = match style.display {
+                Display::None => Display::Flex,
+                Display::Flex => Display::None,
+            };
+        }
     }
 }
 
@@ -241,6 +475,7 @@ impl Plugin for ResultScreenPlugin {
     fn build(&self, app: &mut App) {
         add_update_system!(app, Game, spawn_result);
         add_update_system!(app, Game, scroll_pattern_list);
+        add_update_system!(app, Game, toggle_timing_stats);
         add_update_system!(app, Game, exit_game_state);
         // NOTE: exit処理が散在しているのはよくないかもしれないがとりあえずここで処理することにしておく
         add_exit_system!(app, Game, despawn_game_state);
@@ -1,16 +1,45 @@
 use bevy::prelude::*;
 
-use crate::{add_update_system, events::NoteEvalEvent, resources::score::ScoreResource, AppState};
+use crate::{
+    add_enter_system, add_update_system,
+    events::NoteEvalEvent,
+    resources::{
+        config::{GaugeType, NoFail},
+        life_gauge::LifeGauge,
+        score::{JudgePoint, ScoreResource},
+    },
+    AppState,
+};
 
 fn update_score(mut ev_reader: EventReader<NoteEvalEvent>, mut score: ResMut<ScoreResource>) {
     for ev in ev_reader.iter() {
-        score.update_score(&ev.eval, &ev.note.note_type);
+        score.update_score(&ev.eval, &ev.note.note_type, ev.point);
+        score.update_combo(&ev.eval, &ev.note.note_type);
+        if let Some(timing_error) = ev.timing_error {
+            score.record_timing(ev.note.target_time, timing_error, &ev.eval);
+        }
+    }
+}
+
+/// 選曲画面で選んだ設定でライフゲージを用意する
+fn setup_life_gauge(mut commands: Commands, gauge_type: Res<GaugeType>, no_fail: Res<NoFail>) {
+    commands.insert_resource(LifeGauge::new(*gauge_type, **no_fail));
+}
+
+/// ライフゲージを増減させる. ロングノーツを押している間の加点はゲージに影響しない.
+fn update_life_gauge(mut ev_reader: EventReader<NoteEvalEvent>, mut gauge: ResMut<LifeGauge>) {
+    for ev in ev_reader.iter() {
+        if ev.point != JudgePoint::Tick {
+            gauge.update(&ev.eval);
+        }
     }
 }
 
 pub struct ScorePlugin;
 impl Plugin for ScorePlugin {
     fn build(&self, app: &mut App) {
+        add_enter_system!(app, Game, setup_life_gauge);
         add_update_system!(app, Game, update_score);
+        add_update_system!(app, Game, update_life_gauge);
     }
 }
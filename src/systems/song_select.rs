@@ -1,4 +1,7 @@
-use bevy::prelude::*;
+use std::time::Duration;
+
+use bevy::{asset::LoadState, prelude::*};
+use bevy_kira_audio::prelude::*;
 use itertools::Itertools;
 
 use crate::{
@@ -6,15 +9,20 @@ use crate::{
     components::{
         editor::FrozenChartErrorText,
         song_select::{
-            ActiveSongCard, DifficultyText, SongSelectCard, SongSelectParentNode, SpeedSettingNode,
+            ActiveSongCard, ChartText, DifficultyText, GaugeText, HitErrorBarText,
+            OffsetSettingNode, SongSelectCard, SongSelectParentNode, SpeedSettingNode,
         },
         timer::FrameCounter,
     },
     resources::{
-        config::{GameDifficulty, NoteSpeed},
+        asset_loader::ChartAsset,
+        config::{
+            GameDifficulty, GaugeType, GlobalOffset, HitErrorBar, NoFail, NoteSpeed,
+            UserConfigParser, USER_CONFIG_PATH,
+        },
         game_state::{ExistingEntities, NextAppState},
         handles::SongSelectAssetHandles,
-        song_list::{AllSongData, SongData},
+        song_list::{AllSongData, ChartSummary, PreviewMusic, SelectedChartIndex, SongData},
     },
     spawn_text_node,
     systems::system_labels::TimerSystemLabel,
@@ -22,15 +30,41 @@ use crate::{
 };
 
 const CARD_WIDTH: f32 = 200.0;
+/// 試聴のフェードインの長さ（ミリ秒）
+const PREVIEW_FADE_IN: u64 = 500;
+
+/// 譜面ファイルの中身から選曲画面に表示する情報を求める.
+/// 読み込み中や読み込みに失敗した譜面, 中身が壊れている譜面はNoneとし, 決定時にエラー画面を出す.
+fn chart_summary(
+    handles: &SongSelectAssetHandles,
+    charts: &Assets<ChartAsset>,
+    config_file_name: &str,
+) -> Option<ChartSummary> {
+    handles
+        .charts
+        .get(config_file_name)
+        .and_then(|handle| charts.get(handle))
+        .and_then(|asset| asset.0.as_ref().ok())
+        .map(ChartSummary::from)
+}
 
 fn setup_song_select_scene(
     mut commands: Commands,
     already_exist_q: Query<Entity>,
     handles: Res<SongSelectAssetHandles>,
-    all_song_data: Res<AllSongData>,
+    mut all_song_data: ResMut<AllSongData>,
+    charts: Res<Assets<ChartAsset>>,
 ) {
     // シーン遷移時点で存在しているエンティティをすべて保存
     commands.insert_resource(ExistingEntities(already_exist_q.iter().collect_vec()));
+    // 譜面の中身からBPMや尺を求めておく. まだ読み込まれていない譜面は読み込まれたときに埋める.
+    for chart in all_song_data
+        .0
+        .iter_mut()
+        .flat_map(|data| data.charts.iter_mut())
+    {
+        chart.summary = chart_summary(&handles, &charts, &chart.config_file_name);
+    }
     // 背景を出現
     commands.spawn(SpriteBundle {
         sprite: Sprite {
@@ -105,12 +139,42 @@ fn setup_song_select_scene(
                                 color: Color::GRAY,
                             },
                         ));
+                        // 作曲者, ジャンル, 譜面作者. 書かれていないものは詰める.
+                        let charter = if song_data.charter.is_empty() {
+                            String::new()
+                        } else {
+                            format!("Chart: {}", song_data.charter)
+                        };
+                        let info = [song_data.artist.as_str(), &song_data.genre, &charter]
+                            .into_iter()
+                            .filter(|line| !line.is_empty())
+                            .join("\n");
+                        parent.spawn(TextBundle::from_section(
+                            info,
+                            TextStyle {
+                                font: handles.main_font.clone(),
+                                font_size: 16.0,
+                                color: Color::DARK_GRAY,
+                            },
+                        ));
+                        parent
+                            .spawn(TextBundle::from_section(
+                                "",
+                                TextStyle {
+                                    font: handles.main_font.clone(),
+                                    font_size: 20.0,
+                                    color: Color::DARK_GRAY,
+                                },
+                            ))
+                            .insert(ChartText);
                     });
             }
         });
 
     // 難易度テキスト
     spawn_text_node!(commands, handles.main_font, [right: 10.0, bottom: 20.0], Color::ANTIQUE_WHITE, [["", 30.0, Color::GRAY, [DifficultyText]]], [], {size: Size::new(Val::Px(90.0), Val::Px(40.0))});
+    // ゲージの種類とヒットエラーバーの設定テキスト
+    spawn_text_node!(commands, handles.main_font, [right: 10.0, bottom: 70.0], Color::ANTIQUE_WHITE, [["", 20.0, Color::GRAY, [GaugeText]], ["", 20.0, Color::GRAY, [HitErrorBarText]]], []);
 }
 
 /// Xキーでホームに戻る
@@ -168,6 +232,209 @@ fn reflect_difficulty(
     }
 }
 
+/// Gキーでゲージの種類, Nキーでノーフェイルを切り替える
+fn change_gauge(
+    key_input: Res<Input<KeyCode>>,
+    mut gauge_type: ResMut<GaugeType>,
+    mut no_fail: ResMut<NoFail>,
+) {
+    if key_input.just_pressed(KeyCode::G) {
+        *gauge_type = match *gauge_type {
+            GaugeType::Normal => GaugeType::Hard,
+            GaugeType::Hard => GaugeType::Survival,
+            GaugeType::Survival => GaugeType::Normal,
+        }
+    }
+    if key_input.just_pressed(KeyCode::N) {
+        **no_fail = !**no_fail;
+    }
+}
+
+/// ゲージの設定をテキストに反映する
+fn reflect_gauge(
+    gauge_type: Res<GaugeType>,
+    no_fail: Res<NoFail>,
+    mut text_q: Query<&mut Text, With<GaugeText>>,
+) {
+    if let Ok(mut text) = text_q.get_single_mut() {
+        text.sections[0].value = if **no_fail {
+            format!("Gauge: {} (No Fail)", *gauge_type)
+        } else {
+            format!("Gauge: {}", *gauge_type)
+        };
+        text.sections[0].style.color = gauge_type.get_color();
+    }
+}
+
+/// Bキーでヒットエラーバーの表示を切り替える
+fn toggle_hit_error_bar(key_input: Res<Input<KeyCode>>, mut hit_error_bar: ResMut<HitErrorBar>) {
+    if key_input.just_pressed(KeyCode::B) {
+        **hit_error_bar = !**hit_error_bar;
+    }
+}
+
+fn reflect_hit_error_bar(
+    hit_error_bar: Res<HitErrorBar>,
+    mut text_q: Query<&mut Text, With<HitErrorBarText>>,
+) {
+    if let Ok(mut text) = text_q.get_single_mut() {
+        text.sections[0].value = format!(
+            "Hit Error Bar: {}",
+            if **hit_error_bar { "On" } else { "Off" }
+        );
+    }
+}
+
+/// 上下キーで譜面を選択する. スピードやオフセットの調整中は受け付けない.
+fn change_chart(
+    key_input: Res<Input<KeyCode>>,
+    mut chart_idx: ResMut<SelectedChartIndex>,
+    list_q: Query<&ActiveSongCard>,
+    card_q: Query<(&SongSelectCard, &SongData)>,
+) {
+    if key_input.any_pressed([KeyCode::S, KeyCode::A]) {
+        return;
+    }
+    let Ok(active) = list_q.get_single() else {
+        return;
+    };
+    let Some((_, song_data)) = card_q.iter().find(|(card, _)| card.0 == active.0) else {
+        return;
+    };
+    // 曲ごとに譜面数が異なるので, 選択中の曲の範囲に丸めてから動かす
+    let max_idx = song_data.charts.len() - 1;
+    let current = (**chart_idx).min(max_idx);
+    if key_input.just_pressed(KeyCode::Up) {
+        **chart_idx = (current + 1).min(max_idx);
+    } else if key_input.just_pressed(KeyCode::Down) {
+        **chart_idx = current.saturating_sub(1);
+    }
+}
+
+/// 譜面が読み込まれたり変更されたりしたら, 曲一覧とカードの譜面の情報を求め直す
+fn update_chart_summary(
+    mut ev_reader: EventReader<AssetEvent<ChartAsset>>,
+    handles: Res<SongSelectAssetHandles>,
+    charts: Res<Assets<ChartAsset>>,
+    mut all_song_data: ResMut<AllSongData>,
+    mut card_q: Query<&mut SongData, With<SongSelectCard>>,
+) {
+    let changed = ev_reader
+        .iter()
+        .filter_map(|ev| {
+            let (AssetEvent::Created { handle }
+            | AssetEvent::Modified { handle }
+            | AssetEvent::Removed { handle }) = ev;
+            handles
+                .charts
+                .iter()
+                .find(|(_, h)| *h == handle)
+                .map(|(name, _)| name.clone())
+        })
+        .unique()
+        .collect_vec();
+    if changed.is_empty() {
+        return;
+    }
+    let card_data = card_q.iter_mut().map(|data| data.into_inner());
+    for chart in all_song_data
+        .0
+        .iter_mut()
+        .chain(card_data)
+        .flat_map(|data| data.charts.iter_mut())
+        .filter(|chart| changed.contains(&chart.config_file_name))
+    {
+        chart.summary = chart_summary(&handles, &charts, &chart.config_file_name);
+    }
+}
+
+/// 各カードに選択中の譜面名とレベル, 見積もった難しさ, BPMの範囲と尺を表示する
+fn reflect_chart(
+    chart_idx: Res<SelectedChartIndex>,
+    card_q: Query<(&SongData, &Children)>,
+    mut text_q: Query<&mut Text, With<ChartText>>,
+) {
+    for (song_data, children) in card_q.iter() {
+        let chart = song_data.chart(**chart_idx);
+        let mut value = format!("{} Lv.{}", chart.name, chart.level);
+        if let Some(summary) = chart.summary.as_ref() {
+            value.push_str(&format!(
+                " ({:.1})\nBPM {}  {}",
+                summary.rating,
+                summary.bpm_text(),
+                summary.length_text()
+            ));
+        }
+        for &child in children.iter() {
+            if let Ok(mut text) = text_q.get_mut(child) {
+                text.sections[0].value = value.clone();
+            }
+        }
+    }
+}
+
+/// 選択中の譜面の曲を試聴位置から繰り返し再生する.
+/// カードや譜面を移動して音源か試聴位置が変わったら前の曲を止め, 新しい曲の読み込みが終わってから再生する.
+fn play_preview(
+    mut commands: Commands,
+    audio: Res<Audio>,
+    server: Res<AssetServer>,
+    preview: Option<ResMut<PreviewMusic>>,
+    chart_idx: Res<SelectedChartIndex>,
+    list_q: Query<&ActiveSongCard>,
+    card_q: Query<(&SongSelectCard, &SongData)>,
+) {
+    let Ok(active) = list_q.get_single() else {
+        return;
+    };
+    let Some((_, song_data)) = card_q.iter().find(|(card, _)| card.0 == active.0) else {
+        return;
+    };
+    // 音源のない譜面（キー音のみのBMS等）は試聴しない
+    let music_filename = song_data
+        .chart(**chart_idx)
+        .summary
+        .as_ref()
+        .map(|summary| summary.music_filename.clone())
+        .filter(|filename| !filename.is_empty());
+    let start = song_data.preview_start(**chart_idx);
+    match preview {
+        Some(mut preview) if preview.music_filename == music_filename && preview.start == start => {
+            if preview.started {
+                return;
+            }
+            let Some(handle) = preview.handle.clone() else {
+                return;
+            };
+            if server.get_load_state(&handle) == LoadState::Loaded {
+                audio
+                    .play(handle)
+                    .start_from(start)
+                    .loop_from(start)
+                    .linear_fade_in(Duration::from_millis(PREVIEW_FADE_IN));
+                preview.started = true;
+            }
+        }
+        _ => {
+            audio.stop();
+            let handle = music_filename
+                .as_ref()
+                .map(|filename| server.load(format!("songs/{}", filename)));
+            commands.insert_resource(PreviewMusic {
+                music_filename,
+                start,
+                handle,
+                started: false,
+            });
+        }
+    }
+}
+
+fn stop_preview(mut commands: Commands, audio: Res<Audio>) {
+    audio.stop();
+    commands.remove_resource::<PreviewMusic>();
+}
+
 /// 方向キーでカードを選択する
 fn move_cursor(
     mut list_q: Query<(&mut ActiveSongCard, &mut Style, &Node, &Children)>,
@@ -223,6 +490,7 @@ fn determine_song(
     mut commands: Commands,
     list_q: Query<&ActiveSongCard>,
     card_q: Query<(&SongSelectCard, &SongData)>,
+    chart_idx: Res<SelectedChartIndex>,
     handles: Res<SongSelectAssetHandles>,
     key_input: Res<Input<KeyCode>>,
     mut state: ResMut<State<AppState>>,
@@ -230,9 +498,11 @@ fn determine_song(
     if key_input.just_pressed(KeyCode::Z) {
         if let Ok(active) = list_q.get_single() {
             if let Some((_, song_data)) = card_q.iter().find(|(card, _)| card.0 == active.0) {
-                info!("select song {:?}", song_data);
+                let chart = song_data.chart(**chart_idx);
+                info!("select song {:?}, chart {:?}", song_data.name, chart);
                 // 必要な情報をセットしてからステート移行
                 commands.insert_resource(song_data.clone());
+                commands.insert_resource(chart.clone());
                 // Eキーを押した状態だったら行き先をエディットモードに変更
                 if key_input.pressed(KeyCode::E) {
                     if song_data.edit_freeze {
@@ -324,15 +594,79 @@ fn speed_setting_node(
     }
 }
 
+/// Aキーを押している間, 上下キーで全体オフセットを5ミリ秒刻みで調整する
+fn offset_setting_node(
+    mut commands: Commands,
+    key_input: Res<Input<KeyCode>>,
+    node_q: Query<(&Children, Entity), With<OffsetSettingNode>>,
+    mut text_q: Query<&mut Text>,
+    handles: Res<SongSelectAssetHandles>,
+    mut global_offset: ResMut<GlobalOffset>,
+) {
+    if key_input.pressed(KeyCode::A) {
+        if node_q.is_empty() {
+            spawn_text_node!(
+                commands,
+                handles.main_font,
+                [left: 20.0, bottom: 60.0],
+                Color::ANTIQUE_WHITE,
+                [["", 30.0, Color::BLUE, []]],
+                [OffsetSettingNode],
+                {size: Size::new(Val::Auto, Val::Px(30.0))}
+            );
+        } else {
+            let delta = if key_input.just_pressed(KeyCode::Up) {
+                5.0
+            } else if key_input.just_pressed(KeyCode::Down) {
+                -5.0
+            } else {
+                0.0
+            };
+            if delta != 0.0 {
+                **global_offset = (**global_offset + delta).clamp(-500.0, 500.0);
+                // 次回の起動でも同じオフセットを使えるように保存する
+                let config = UserConfigParser {
+                    global_offset: **global_offset,
+                };
+                if let Err(e) = config.save(USER_CONFIG_PATH) {
+                    warn!("failed to save user config: {}", e);
+                }
+            }
+            let (children, _) = node_q.get_single().unwrap();
+            for &child in children.iter() {
+                if let Ok(mut text) = text_q.get_mut(child) {
+                    text.sections[0].value = format!("OFFSET: {:+} ms", **global_offset);
+                }
+            }
+        }
+    } else {
+        for (_, ent) in node_q.iter() {
+            commands.entity(ent).despawn_recursive();
+        }
+    }
+}
+
 pub struct SongSelectStatePlugin;
 impl Plugin for SongSelectStatePlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedChartIndex>()
+            .init_resource::<GaugeType>()
+            .init_resource::<NoFail>()
+            .init_resource::<HitErrorBar>();
         add_enter_system!(app, SongSelect, setup_song_select_scene);
         add_update_system!(app, SongSelect, back_to_home_menu);
         add_update_system!(app, SongSelect, hover_card);
         add_update_system!(app, SongSelect, change_difficulty);
         add_update_system!(app, SongSelect, reflect_difficulty);
+        add_update_system!(app, SongSelect, change_gauge);
+        add_update_system!(app, SongSelect, reflect_gauge);
+        add_update_system!(app, SongSelect, toggle_hit_error_bar);
+        add_update_system!(app, SongSelect, reflect_hit_error_bar);
         add_update_system!(app, SongSelect, move_cursor);
+        add_update_system!(app, SongSelect, change_chart);
+        add_update_system!(app, SongSelect, update_chart_summary);
+        add_update_system!(app, SongSelect, reflect_chart);
+        add_update_system!(app, SongSelect, play_preview);
         add_update_system!(
             app,
             SongSelect,
@@ -341,6 +675,8 @@ impl Plugin for SongSelectStatePlugin {
         );
         add_update_system!(app, SongSelect, determine_song);
         add_update_system!(app, SongSelect, speed_setting_node);
+        add_update_system!(app, SongSelect, offset_setting_node);
         add_exit_system!(app, SongSelect, despawn_song_select_scene);
+        add_exit_system!(app, SongSelect, stop_preview);
     }
 }
@@ -24,6 +24,12 @@ pub(super) enum PatternReceptorSystemLabel {
     Recept,
 }
 
+#[derive(SystemLabel)]
+pub(super) enum LoadSystemLabel {
+    /// 曲一覧や譜面の内容に依存するリソースを用意する
+    Prepare,
+}
+
 #[derive(SystemLabel)]
 pub(super) enum EditorSystemLabel {
     UpdateBarAndBeat,
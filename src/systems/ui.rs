@@ -1,4 +1,7 @@
-use bevy::{prelude::*, sprite::Mesh2dHandle};
+use bevy::{
+    prelude::*,
+    sprite::{Anchor, Mesh2dHandle},
+};
 use itertools::Itertools;
 use rand::Rng;
 
@@ -8,16 +11,21 @@ use crate::{
         note::KeyLane,
         timer::{CountDownTimer, FrameCounter},
         ui::{
-            CatchEvalPopupText, ChartInfoNode, GameStateObject, LaneLine, PatternPopupText,
-            ScoreText, TargetLine,
+            CatchEvalPopupText, ChartInfoNode, ComboText, GameStateObject, HitErrorTick, LaneLine,
+            LifeGaugeBar, PatternPopupText, ScoreText, TargetLine,
         },
     },
-    constants::{LANE_WIDTH, TARGET_Y},
+    constants::{
+        HIT_ERROR_BAR_OFFSET, HIT_ERROR_BAR_WIDTH, HIT_ERROR_TICK_LIFETIME, LANE_WIDTH,
+        LIFE_GAUGE_HEIGHT, LIFE_GAUGE_WIDTH, TARGET_Y,
+    },
     events::{AchievePatternEvent, NoteEvalEvent},
     resources::{
-        config::GameDifficulty,
+        config::{GameDifficulty, GaugeType, HitErrorBar, LaneNum},
         game_state::ExistingEntities,
         handles::GameAssetsHandles,
+        judge::JudgeProfile,
+        life_gauge::LifeGauge,
         note::NoteType,
         score::{CatchEval, ScoreResource, TimingEval},
         song::SongConfigResource,
@@ -79,7 +87,9 @@ fn setup_ui(
     mut commands: Commands,
     song_config: Res<SongConfigResource>,
     diff: Res<GameDifficulty>,
+    gauge_type: Res<GaugeType>,
     handles: Res<GameAssetsHandles>,
+    lane_num: Res<LaneNum>,
 ) {
     let font = handles.main_font.clone();
     // 曲名・難易度表示ノード
@@ -109,6 +119,15 @@ fn setup_ui(
         ]],
         [GameStateObject]
     );
+    // コンボ表示テキストノード
+    spawn_text_node!(
+        commands,
+        font,
+        [right: 20.0, top: 10.0],
+        Color::NONE,
+        [["", 50.0, Color::WHITE, [ComboText]]],
+        [GameStateObject]
+    );
     // NOTE: マクロの展開は以下のようになることを示すためここは残しておく.
     // commands
     //     .spawn(NodeBundle {
@@ -160,8 +179,8 @@ fn setup_ui(
         .insert(GameStateObject);
 
     // 鍵盤線
-    for i in 0..5 {
-        let x = KeyLane::x_coord_from_num(i);
+    for i in 0..=**lane_num as i32 {
+        let x = KeyLane::x_coord_from_num(i, **lane_num);
         let transform = Transform {
             translation: Vec3::new(x - LANE_WIDTH / 2.0, TARGET_Y + 250.0, 2.0),
             ..Default::default()
@@ -176,17 +195,46 @@ fn setup_ui(
             .insert(LaneLine)
             .insert(GameStateObject);
     }
+
+    // ライフゲージ. 枠の上に残量のバーを重ねる.
+    let gauge_x = **lane_num as f32 * LANE_WIDTH / 2.0 + 20.0;
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.2, 0.2, 0.2, 0.7),
+                custom_size: Some(Vec2::new(LIFE_GAUGE_WIDTH, LIFE_GAUGE_HEIGHT)),
+                anchor: Anchor::BottomCenter,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(gauge_x, TARGET_Y, 2.0),
+            ..Default::default()
+        })
+        .insert(GameStateObject);
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: gauge_type.get_color(),
+                custom_size: Some(Vec2::new(LIFE_GAUGE_WIDTH, LIFE_GAUGE_HEIGHT)),
+                anchor: Anchor::BottomCenter,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(gauge_x, TARGET_Y, 2.1),
+            ..Default::default()
+        })
+        .insert(LifeGaugeBar)
+        .insert(GameStateObject);
 }
 
 fn setup_lane(
     mut commands: Commands,
     handles: Res<GameAssetsHandles>,
+    lane_num: Res<LaneNum>,
     already_exist_q: Query<Entity>,
 ) {
     // シーン遷移時点で存在しているエンティティをすべて保存
     commands.insert_resource(ExistingEntities(already_exist_q.iter().collect_vec()));
-    for i in 0..4 {
-        let x = KeyLane::x_coord_from_num(i);
+    for i in 0..**lane_num as i32 {
+        let x = KeyLane::x_coord_from_num(i, **lane_num);
         let transform = Transform {
             translation: Vec3::new(x, TARGET_Y + 250.0, 0.1),
             ..Default::default()
@@ -198,7 +246,7 @@ fn setup_lane(
                 transform,
                 ..Default::default()
             })
-            .insert(KeyLane(i))
+            .insert(KeyLane::new(i, **lane_num))
             .insert(GameStateObject)
             .insert(FrameCounter::new_default(60));
     }
@@ -221,20 +269,122 @@ fn setup_lane(
 fn update_score_text(score: Res<ScoreResource>, mut query: Query<(&mut Text, &ScoreText)>) {
     if score.is_changed() {
         for (mut text, _marker) in query.iter_mut() {
+            // まだ何も判定していないときは精度を表示しない
+            let accuracy = match (score.get_accuracy(), score.get_grade()) {
+                (Some(accuracy), Some(grade)) => format!("{:.2}% ({})", accuracy * 100.0, grade),
+                _ => "--".to_string(),
+            };
             text.sections[0].value = format!(
-                "Score: {}. Perfect: {}. Ok: {}. Miss: {}.",
+                "Score: {}. Perfect: {}. Ok: {}. Miss: {}.\nAccuracy: {}",
                 score.get_score(),
                 score.get_eval_num(&CatchEval::Perfect)
                     + score.get_eval_num(&CatchEval::NearPerfect(TimingEval::Fast))
                     + score.get_eval_num(&CatchEval::NearPerfect(TimingEval::Slow)),
                 score.get_eval_num(&CatchEval::Ok(TimingEval::Fast))
                     + score.get_eval_num(&CatchEval::Ok(TimingEval::Slow)),
-                score.get_eval_num(&CatchEval::Miss)
+                score.get_eval_num(&CatchEval::Miss),
+                accuracy
             );
         }
     }
 }
 
+/// コンボ数を表示する. 2コンボ以上続いているときのみ表示する.
+fn update_combo_text(score: Res<ScoreResource>, mut query: Query<&mut Text, With<ComboText>>) {
+    if score.is_changed() {
+        for mut text in query.iter_mut() {
+            text.sections[0].value = match score.get_combo() {
+                combo if combo >= 2 => format!("{} combo", combo),
+                _ => String::new(),
+            };
+        }
+    }
+}
+
+/// 判定線の下にヒットエラーバーの枠と誤差0の位置を示す線を出す
+fn setup_hit_error_bar(mut commands: Commands, hit_error_bar: Res<HitErrorBar>) {
+    if !**hit_error_bar {
+        return;
+    }
+    let y = TARGET_Y - HIT_ERROR_BAR_OFFSET;
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.2, 0.2, 0.2, 0.7),
+                custom_size: Some(Vec2::new(HIT_ERROR_BAR_WIDTH, 8.0)),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0.0, y, 2.0),
+            ..Default::default()
+        })
+        .insert(GameStateObject);
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::WHITE,
+                custom_size: Some(Vec2::new(2.0, 20.0)),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0.0, y, 2.1),
+            ..Default::default()
+        })
+        .insert(GameStateObject);
+}
+
+/// 叩いた時刻の誤差の位置にヒットエラーバーの目盛りを出す. 左が早く, 右が遅い.
+fn spawn_hit_error_tick(
+    mut commands: Commands,
+    mut ev_reader: EventReader<NoteEvalEvent>,
+    hit_error_bar: Res<HitErrorBar>,
+    judge: Res<JudgeProfile>,
+) {
+    if !**hit_error_bar {
+        ev_reader.clear();
+        return;
+    }
+    let range = judge.base_window().ok;
+    for ev in ev_reader.iter() {
+        let Some(timing_error) = ev.timing_error else {
+            continue;
+        };
+        let x = (timing_error / range).clamp(-1.0, 1.0) as f32 * HIT_ERROR_BAR_WIDTH / 2.0;
+        commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: ev.eval.get_color(),
+                    custom_size: Some(Vec2::new(3.0, 16.0)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(x, TARGET_Y - HIT_ERROR_BAR_OFFSET, 2.2),
+                ..Default::default()
+            })
+            .insert(CountDownTimer::new(HIT_ERROR_TICK_LIFETIME))
+            .insert(HitErrorTick)
+            .insert(GameStateObject);
+    }
+}
+
+/// ヒットエラーバーの目盛りを時間とともに薄くする. 消去はタイマーが行う.
+fn update_hit_error_tick(mut tick_q: Query<(&mut Sprite, &CountDownTimer), With<HitErrorTick>>) {
+    for (mut sprite, timer) in tick_q.iter_mut() {
+        sprite
+            .color
+            .set_a(timer.count() as f32 / HIT_ERROR_TICK_LIFETIME as f32);
+    }
+}
+
+/// ライフゲージの残量をバーの高さに反映する
+fn update_life_gauge_bar(gauge: Res<LifeGauge>, mut query: Query<&mut Sprite, With<LifeGaugeBar>>) {
+    if gauge.is_changed() {
+        for mut sprite in query.iter_mut() {
+            sprite.custom_size = Some(Vec2::new(
+                LIFE_GAUGE_WIDTH,
+                LIFE_GAUGE_HEIGHT * gauge.get_value() as f32,
+            ));
+        }
+    }
+}
+
 fn update_lane_background(
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut query: Query<(&Handle<ColorMaterial>, &KeyLane, &mut FrameCounter)>,
@@ -304,13 +454,14 @@ fn spawn_catch_eval_text(
     mut commands: Commands,
     mut ev_reader: EventReader<NoteEvalEvent>,
     handles: Res<GameAssetsHandles>,
+    lane_num: Res<LaneNum>,
 ) {
     let font = handles.main_font.clone();
     for ev in ev_reader.iter() {
         // イベントに含まれているノーツ情報から評価を出現させる位置を計算.
         let get_pos_closure = |key: i32| {
             Vec2::new(
-                SCREEN_WIDTH / 2.0 + KeyLane::x_coord_from_num(key) - LANE_WIDTH / 2.0,
+                SCREEN_WIDTH / 2.0 + KeyLane::x_coord_from_num(key, **lane_num) - LANE_WIDTH / 2.0,
                 SCREEN_HEIGHT / 2.0 + TARGET_Y,
             )
         };
@@ -326,6 +477,13 @@ fn spawn_catch_eval_text(
             NoteType::Long { key, length: _, id: _ } => {
                 Some(get_pos_closure(key))
             }
+            NoteType::Mine { key } => {
+                Some(get_pos_closure(key))
+            }
+            NoteType::Roll { key, length: _, id: _ } => {
+                Some(get_pos_closure(key))
+            }
+            NoteType::BpmChange { bpm: _ } | NoteType::BeatChange { beat: _ } | NoteType::KeySound { sound: _ } => None,
         }) else { continue };
 
         if let Some(timing) = ev.eval.get_timing() {
@@ -390,11 +548,26 @@ impl Plugin for GameUiPlugin {
     fn build(&self, app: &mut App) {
         add_enter_system!(app, Game, setup_ui);
         add_enter_system!(app, Game, setup_lane);
+        add_enter_system!(app, Game, setup_hit_error_bar);
         // app.add_system_set(
         //     SystemSet::on_update(AppState::Game)
         //         .with_system(update_time_text.label(TimerSystemLabel::StartAudio)),
         // );
         add_update_system!(app, Game, update_score_text);
+        add_update_system!(app, Game, update_combo_text);
+        add_update_system!(app, Game, update_life_gauge_bar);
+        add_update_system!(
+            app,
+            Game,
+            spawn_hit_error_tick,
+            [after: TimerSystemLabel::TimerUpdate]
+        );
+        add_update_system!(
+            app,
+            Game,
+            update_hit_error_tick,
+            [after: TimerSystemLabel::TimerUpdate]
+        );
         add_update_system!(
             app,
             Game,